@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use snek::{Cell, RingTail};
+use std::collections::VecDeque;
+use std::iter::FromIterator;
+
+const TAIL_LENGTH : usize = 100;
+const GROWN_LENGTH : usize = 500;
+
+fn line<T: FromIterator<Cell>>(length: usize) -> T {
+  (0..length as i32).map(|x| Cell::new(x, 0)).collect()
+}
+
+// What update_position does to the tail every move.
+fn moving(c: &mut Criterion) {
+  let mut group = c.benchmark_group("tail move");
+  let mut deque: VecDeque<Cell> = line(TAIL_LENGTH);
+  group.bench_function("VecDeque", |b| b.iter(|| {
+    deque.push_front(black_box(Cell::new(0, 1)));
+    deque.pop_back()
+  }));
+  let mut ring: RingTail = line(TAIL_LENGTH);
+  group.bench_function("RingTail", |b| b.iter(|| {
+    ring.push_front(black_box(Cell::new(0, 1)));
+    ring.pop_back()
+  }));
+  group.finish();
+}
+
+// A fresh snake eating its way up to a long tail, one cell at a time.
+fn growing(c: &mut Criterion) {
+  let mut group = c.benchmark_group("tail grow");
+  group.bench_function("VecDeque", |b| b.iter(|| {
+    let mut deque = VecDeque::new();
+    for x in 0..GROWN_LENGTH as i32 {
+      deque.push_back(black_box(Cell::new(x, 0)));
+    }
+    deque
+  }));
+  group.bench_function("RingTail", |b| b.iter(|| {
+    let mut ring = RingTail::new();
+    for x in 0..GROWN_LENGTH as i32 {
+      ring.push_back(black_box(Cell::new(x, 0)));
+    }
+    ring
+  }));
+  group.finish();
+}
+
+// The far end of the tail is the worst case for occupies.
+fn searching(c: &mut Criterion) {
+  let mut group = c.benchmark_group("tail contains tip");
+  let tip = Cell::new(TAIL_LENGTH as i32 - 1, 0);
+  let deque: VecDeque<Cell> = line(TAIL_LENGTH);
+  group.bench_function("VecDeque", |b| b.iter(|| deque.contains(black_box(&tip))));
+  let ring: RingTail = line(TAIL_LENGTH);
+  group.bench_function("RingTail", |b| b.iter(|| ring.contains(black_box(&tip))));
+  group.finish();
+}
+
+// The AI copies a snake for every move it looks ahead at.
+fn copying(c: &mut Criterion) {
+  let mut group = c.benchmark_group("tail clone");
+  let deque: VecDeque<Cell> = line(TAIL_LENGTH);
+  group.bench_function("VecDeque", |b| b.iter(|| black_box(&deque).clone()));
+  let ring: RingTail = line(TAIL_LENGTH);
+  group.bench_function("RingTail", |b| b.iter(|| black_box(&ring).clone()));
+  group.finish();
+}
+
+criterion_group!(benches, moving, growing, searching, copying);
+criterion_main!(benches);
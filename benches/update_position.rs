@@ -0,0 +1,30 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use snek::{Cell, Dir, Player};
+
+const TAIL_LENGTH : usize = 100;
+
+// Heading right with the tail trailing off to the left.
+fn long_snake() -> Player {
+  Player::new_with_length(TAIL_LENGTH as i32, 0, TAIL_LENGTH + 1, Dir::Right)
+}
+
+fn update_position(c: &mut Criterion) {
+  c.bench_function("update_position", |b| {
+    let mut player = long_snake();
+    b.iter(|| {
+      player.update_position();
+      black_box(&player);
+    })
+  });
+}
+
+fn occupies(c: &mut Criterion) {
+  let player = long_snake();
+  // The far end of the tail is the worst case for a linear search.
+  let tip = *player.tail.back().unwrap();
+  c.bench_function("occupies tail tip", |b| b.iter(|| player.occupies(black_box(tip))));
+  c.bench_function("occupies free cell", |b| b.iter(|| player.occupies(black_box(Cell::new(-1, -1)))));
+}
+
+criterion_group!(benches, update_position, occupies);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+#!/bin/sh
+# Builds the browser version into web/. Needs the wasm32-unknown-unknown
+# target and a wasm-bindgen-cli matching the wasm-bindgen in Cargo.lock:
+#   rustup target add wasm32-unknown-unknown
+#   cargo install wasm-bindgen-cli
+# Serve web/ over http (file:// won't load the .wasm), for example with
+#   python3 -m http.server --directory web
+set -e
+cargo build --release --target wasm32-unknown-unknown
+wasm-bindgen target/wasm32-unknown-unknown/release/main.wasm \
+  --out-dir web --no-modules --no-typescript
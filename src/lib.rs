@@ -0,0 +1,3350 @@
+// https://users.rust-lang.org/t/usage-of-extern-crate/73619
+
+use bracket_lib::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod logic;
+#[cfg(feature = "ring-tail")]
+mod ring_tail;
+
+pub use logic::{Event, GameLogic};
+#[cfg(feature = "ring-tail")]
+pub use ring_tail::RingTail;
+
+const SCREEN_WIDTH : i32 = 48;
+const SCREEN_HEIGHT : i32 = 48;
+// Characters reserved around the board for the border.
+const BORDER_SIZE : i32 = 1;
+const BORDER_CONSOLE : usize = 1;
+const GHOST_CONSOLE : usize = 2;
+// The score line, banners and debug overlay, on top of everything else.
+const HUD_CONSOLE : usize = 3;
+// How many of the ghost's latest positions are shown.
+const GHOST_TRAIL : usize = 4;
+const MAX_SPAWN_ATTEMPTS : usize = 1000;
+// Every this many points knocks one tick off the move interval.
+const POINTS_PER_SPEEDUP : i32 = 5;
+const INPUT_BUFFER_SIZE : usize = 2;
+// One in this many food items is poison.
+const POISON_ODDS : i32 = 5;
+// A bonus item shows up every BONUS_INTERVAL ticks and stays for BONUS_LIFETIME.
+const BONUS_INTERVAL : u64 = 100;
+const BONUS_LIFETIME : u64 = 50;
+const BONUS_POINTS : i32 = 5;
+// Food blinks on and off with a period of this many ticks, then half that
+// in its last 2*FOOD_BLINK_TICKS and the minimum in its last FOOD_BLINK_TICKS.
+const FOOD_BLINK_TICKS : u64 = 20;
+const MIN_FOOD_BLINK_TICKS : u64 = 4;
+// Food nobody eats moves somewhere else after this many ticks, in case
+// the snake has cut it off.
+const FOOD_LIFETIME : u64 = 200;
+const FOOD_POINTS : i32 = 1;
+// Eating again within COMBO_WINDOW ticks raises the points multiplier, up to MAX_COMBO.
+const COMBO_WINDOW : u64 = 20;
+const MAX_COMBO : u32 = 5;
+// Every STRAIGHT_MOVES_PER_BONUS moves without turning adds one to the
+// points multiplier, up to MAX_STRAIGHT_BONUS.
+const STRAIGHT_MOVES_PER_BONUS : u32 = 10;
+const MAX_STRAIGHT_BONUS : u32 = 5;
+// Ticks between each step of the moving food.
+const MOBILE_FOOD_INTERVAL : u64 = 12;
+const PORTAL_PAIRS : usize = 2;
+// Another OBSTACLE_BATCH obstacles are added every POINTS_PER_OBSTACLES points.
+const POINTS_PER_OBSTACLES : i32 = 10;
+const OBSTACLE_BATCH : usize = 3;
+// Food to eat before moving on to the next level.
+const FOOD_PER_LEVEL : u32 = 10;
+// A moving wall joins the board from this level on, two on Insane. Each
+// is a bar this many cells high that slides one cell every so many ticks.
+const MOVING_WALL_LEVEL : u32 = 3;
+const MOVING_WALL_LENGTH : i32 = 3;
+const MOVING_WALL_SPEED : u64 = 12;
+// Wall layouts for a LEVEL_SIZE x LEVEL_SIZE board, one per level, starting
+// over after the last one. Bigger boards get them scaled up, see
+// Board::fit_layout. Walls on a spawn point or off a smaller board are
+// dropped.
+const LEVEL_SIZE : i32 = 15;
+const LEVELS : &[&[Cell]] = &[
+  &[],
+  // Pillars
+  &[
+    Cell::new(3, 3), Cell::new(4, 3), Cell::new(3, 4), Cell::new(4, 4),
+    Cell::new(10, 3), Cell::new(11, 3), Cell::new(10, 4), Cell::new(11, 4),
+    Cell::new(3, 10), Cell::new(4, 10), Cell::new(3, 11), Cell::new(4, 11),
+    Cell::new(10, 10), Cell::new(11, 10), Cell::new(10, 11), Cell::new(11, 11),
+  ],
+  // Side bars
+  &[
+    Cell::new(3, 4), Cell::new(3, 5), Cell::new(3, 6), Cell::new(3, 7),
+    Cell::new(3, 8), Cell::new(3, 9), Cell::new(3, 10),
+    Cell::new(11, 4), Cell::new(11, 5), Cell::new(11, 6), Cell::new(11, 7),
+    Cell::new(11, 8), Cell::new(11, 9), Cell::new(11, 10),
+  ],
+  // Top and bottom bars
+  &[
+    Cell::new(4, 1), Cell::new(5, 1), Cell::new(6, 1), Cell::new(7, 1),
+    Cell::new(8, 1), Cell::new(9, 1), Cell::new(10, 1),
+    Cell::new(4, 13), Cell::new(5, 13), Cell::new(6, 13), Cell::new(7, 13),
+    Cell::new(8, 13), Cell::new(9, 13), Cell::new(10, 13),
+  ],
+  // Corners
+  &[
+    Cell::new(1, 1), Cell::new(2, 1), Cell::new(3, 1), Cell::new(1, 2), Cell::new(1, 3),
+    Cell::new(13, 1), Cell::new(12, 1), Cell::new(11, 1), Cell::new(13, 2), Cell::new(13, 3),
+    Cell::new(1, 13), Cell::new(2, 13), Cell::new(3, 13), Cell::new(1, 12), Cell::new(1, 11),
+    Cell::new(13, 13), Cell::new(12, 13), Cell::new(11, 13), Cell::new(13, 12), Cell::new(13, 11),
+  ],
+  // Dashes
+  &[
+    Cell::new(2, 5), Cell::new(3, 5), Cell::new(4, 5),
+    Cell::new(10, 5), Cell::new(11, 5), Cell::new(12, 5),
+    Cell::new(2, 9), Cell::new(3, 9), Cell::new(4, 9),
+    Cell::new(10, 9), Cell::new(11, 9), Cell::new(12, 9),
+    Cell::new(7, 1), Cell::new(7, 13), Cell::new(1, 7), Cell::new(13, 7),
+  ],
+];
+// The game runs at this many ticks per second, however fast it is drawn.
+const TICKS_PER_SECOND : u64 = 60;
+const TICK_MS : f64 = 1000.0 / TICKS_PER_SECOND as f64;
+// After a long frame the game catches up by at most this many ticks.
+const MAX_TICKS_PER_FRAME : u32 = 5;
+const POWER_UP_INTERVAL : u64 = 300;
+const POWER_UP_DURATION : u64 = 5 * TICKS_PER_SECOND;
+const INVINCIBLE_DURATION : u64 = 30;
+// Points per snake segment for lasting a whole time attack game.
+const COUNTDOWN_TICKS : u64 = 3 * TICKS_PER_SECOND;
+// Length of the wipe from the board to the end screens.
+const TRANSITION_TICKS : u8 = 15;
+// Ticks the board shakes for after a snake loses a life.
+const SHAKE_TICKS : u8 = 10;
+// Ticks a flash stays where food was eaten.
+const EAT_FLASH_TICKS : u8 = 5;
+// How long the banner for a newly unlocked achievement stays up.
+const ACHIEVEMENT_BANNER_TICKS : u64 = 60;
+// In survival games the snake grows once every this many ticks.
+const SURVIVAL_GROWTH_TICKS : u64 = 30;
+// Challenge games have one food of each colour, to be eaten in this order.
+const CHALLENGE_COLORS : [(&str, (u8, u8, u8)); 4] = [
+  ("Red", RED), ("Green", GREEN), ("Blue", BLUE), ("Yellow", YELLOW),
+];
+// Points lost for eating a challenge food out of turn.
+const CHALLENGE_PENALTY : i32 = 3;
+// What pressing H costs, and how long its arrow stays up.
+const HINT_COST : i32 = 5;
+const HINT_TICKS : u64 = 2 * TICKS_PER_SECOND;
+// How long the player keeps control in training mode after their last turn.
+const TAKEOVER_TICKS : u64 = 5 * TICKS_PER_SECOND;
+const MAX_NAME_LEN : usize = 8;
+const LEADERBOARD_SIZE : usize = 10;
+const TIME_BONUS_PER_SEGMENT : i32 = 2;
+// The choices on the settings screen, each with the GameConfig value it sets.
+const SPEED_SETTINGS : [(&str, u64); 3] = [("Slow", 8), ("Normal", 6), ("Fast", 4)];
+// A bigger pixel scale leaves room for fewer cells.
+const BOARD_SETTINGS : [(&str, i32); 4] = [("Small", 4), ("Medium", 3), ("Large", 2), ("Huge", 1)];
+// The most food settings.toml may put on the board at once.
+const MAX_FOOD_COUNT : usize = 20;
+const DIFFICULTY_SETTINGS : [(&str, Difficulty); 4] = [
+  ("Easy", Difficulty::Easy),
+  ("Normal", Difficulty::Normal),
+  ("Hard", Difficulty::Hard),
+  ("Insane", Difficulty::Insane),
+];
+const THEME_SETTINGS : [(&str, Theme); 4] = [
+  ("Classic", Theme::Classic),
+  ("Ocean", Theme::Ocean),
+  ("Forest", Theme::Forest),
+  ("High contrast", Theme::HighContrast),
+];
+const BACKGROUND_SETTINGS : [(&str, BackgroundStyle); 3] = [
+  ("Solid", BackgroundStyle::Solid),
+  ("Checkered", BackgroundStyle::Checkered),
+  ("Dot grid", BackgroundStyle::DotGrid),
+];
+// Power-ups never appear closer than this to the head.
+const POWER_UP_MIN_DISTANCE : i32 = 3;
+// Food always appears at least this far from the head of the snake.
+const FOOD_MIN_DISTANCE : i32 = 3;
+// The most characters --width and --height may make the screen across
+// or down.
+const MAX_SCREEN_SIZE : i32 = 400;
+const USAGE : &str = "usage: snek [--width CELLS] [--height CELLS] [--seed HEX] \
+  [--difficulty easy|normal|hard|insane] [--high-contrast] [--load FILE]";
+
+// Saved to ~/.config/snek/settings.toml from the settings screen. The
+// screen size and seed are not settings, so they are never saved.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+struct GameConfig {
+  #[serde(skip)]
+  pub screen_width: i32,
+  #[serde(skip)]
+  pub screen_height: i32,
+  // The snake moves once every this many ticks at the start of a game.
+  pub ticks_per_move: u64,
+  // Each cell is drawn as a pixel_scale x pixel_scale block of characters.
+  pub pixel_scale: i32,
+  // Ticks knocked off ticks_per_move before any points are scored.
+  pub initial_speed: u64,
+  // Food seed for every game, a fresh one is picked per game when unset.
+  #[serde(skip)]
+  pub seed: Option<u64>,
+  pub lives: u8,
+  // How many food items are on the board at once.
+  pub food_count: usize,
+  // How long a time attack game lasts.
+  pub time_limit_ticks: u64,
+  // Obstacles on the board before any points are scored.
+  pub initial_obstacles: usize,
+  // Cells in each snake, head included, at the start of a game.
+  pub initial_length: usize,
+  // The preset the rest of the settings started from.
+  pub difficulty: Difficulty,
+  pub theme: Theme,
+  pub background: BackgroundStyle
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Difficulty {
+  Easy,
+  Normal,
+  Hard,
+  // Fast, crowded and only the one life.
+  Insane
+}
+
+impl Difficulty {
+  fn from_name(name: &str) -> Option<Self> {
+    DIFFICULTY_SETTINGS.iter().find(|(choice, _)| *choice == name).map(|(_, difficulty)| *difficulty)
+  }
+
+  // Like from_name, but takes "hard" as well as "Hard".
+  fn from_arg(arg: &str) -> Option<Self> {
+    DIFFICULTY_SETTINGS.iter()
+      .find(|(choice, _)| choice.eq_ignore_ascii_case(arg))
+      .map(|(_, difficulty)| *difficulty)
+  }
+
+  fn name(self) -> &'static str {
+    setting_name(&DIFFICULTY_SETTINGS, self)
+  }
+
+  fn allows_hints(self) -> bool {
+    matches!(self, Difficulty::Easy | Difficulty::Normal)
+  }
+
+  // Applied to every point scored from food, so the leaderboard, which is
+  // shared between difficulties, ranks harder games higher. The speed-ups
+  // and obstacles go by the score without it.
+  fn score_multiplier(self) -> i32 {
+    match self {
+      Difficulty::Easy | Difficulty::Normal => 1,
+      Difficulty::Hard => 2,
+      Difficulty::Insane => 3,
+    }
+  }
+
+  fn config(self) -> GameConfig {
+    let normal = GameConfig::default();
+    let (ticks_per_move, lives, food_count, initial_obstacles) = match self {
+      Difficulty::Easy => (8, 5, 4, 0),
+      Difficulty::Normal => (normal.ticks_per_move, normal.lives, normal.food_count, 0),
+      Difficulty::Hard => (4, 2, 2, 3),
+      Difficulty::Insane => (3, 1, 1, 5),
+    };
+    GameConfig { ticks_per_move, lives, food_count, initial_obstacles, difficulty: self, ..normal }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Theme {
+  Classic,
+  Ocean,
+  Forest,
+  // White on black with solid magenta food, for the clearest contrast.
+  HighContrast
+}
+
+// What is drawn on the empty cells of the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum BackgroundStyle {
+  Solid,
+  Checkered,
+  DotGrid
+}
+
+// The colours the board is drawn in. Only the first player's snake takes
+// its colour from the theme, the others keep theirs to stay told apart.
+struct ColorTheme {
+  pub snake_head: (u8, u8, u8),
+  // Where the fade along the tail ends up at the tip.
+  pub snake_tail: (u8, u8, u8),
+  pub food: (u8, u8, u8),
+  pub food_glyph: char,
+  // Kept apart from the snake head, since both move.
+  pub mobile_food: (u8, u8, u8),
+  pub background: (u8, u8, u8),
+  pub obstacle: (u8, u8, u8),
+  // The HUD under the board.
+  pub text: (u8, u8, u8)
+}
+
+impl Theme {
+  fn name(self) -> &'static str {
+    setting_name(&THEME_SETTINGS, self)
+  }
+
+  fn colors(self) -> ColorTheme {
+    match self {
+      Theme::Classic => ColorTheme {
+        snake_head: YELLOW,
+        snake_tail: (64, 64, 0),
+        food: PINK,
+        food_glyph: '@',
+        mobile_food: LIME_GREEN,
+        background: BLACK,
+        obstacle: GREY,
+        text: WHITE
+      },
+      Theme::Ocean => ColorTheme {
+        snake_head: CYAN,
+        snake_tail: (0, 60, 90),
+        food: CORAL,
+        food_glyph: '@',
+        mobile_food: LIME_GREEN,
+        background: (0, 0, 48),
+        obstacle: STEEL_BLUE,
+        text: WHITE
+      },
+      Theme::Forest => ColorTheme {
+        snake_head: LIME_GREEN,
+        snake_tail: (20, 60, 20),
+        food: ORANGE,
+        food_glyph: '@',
+        mobile_food: SKYBLUE1,
+        background: (0, 32, 0),
+        obstacle: SADDLE_BROWN,
+        text: WHITE
+      },
+      Theme::HighContrast => ColorTheme {
+        snake_head: WHITE,
+        snake_tail: (160, 160, 160),
+        food: MAGENTA,
+        food_glyph: '█',
+        mobile_food: LIME_GREEN,
+        background: BLACK,
+        obstacle: (0, 160, 255),
+        text: YELLOW
+      },
+    }
+  }
+}
+
+impl Default for GameConfig {
+  fn default() -> Self {
+    GameConfig {
+      screen_width: SCREEN_WIDTH,
+      screen_height: SCREEN_HEIGHT,
+      ticks_per_move: 6,
+      pixel_scale: 3,
+      initial_speed: 0,
+      seed: None,
+      lives: 3,
+      food_count: 3,
+      time_limit_ticks: 60 * TICKS_PER_SECOND,
+      initial_obstacles: 0,
+      initial_length: 2,
+      difficulty: Difficulty::Normal,
+      theme: Theme::Classic,
+      background: BackgroundStyle::Solid
+    }
+  }
+}
+
+impl GameConfig {
+  // The row under the border is kept for the HUD.
+  fn board(&self) -> Board {
+    Board {
+      width: (self.screen_width - 2*BORDER_SIZE) / self.pixel_scale,
+      height: (self.screen_height - 2*BORDER_SIZE - 1) / self.pixel_scale,
+      pixel_scale: self.pixel_scale
+    }
+  }
+
+  // Sizes the screen to fit a board of width x height cells at the
+  // current pixel scale. The menus are laid out for the default screen, so
+  // the board can't be smaller than the one that fills it.
+  fn set_board_size(&mut self, width: i32, height: i32) -> Result<(), String> {
+    let min = GameConfig { screen_width: SCREEN_WIDTH, screen_height: SCREEN_HEIGHT, ..*self }.board();
+    if width < min.width || height < min.height {
+      return Err(format!("the board must be at least {}x{} cells", min.width, min.height));
+    }
+    let size = |cells: i32, extra: i32| {
+      cells.checked_mul(self.pixel_scale)
+        .and_then(|chars| chars.checked_add(extra))
+        .filter(|&chars| chars <= MAX_SCREEN_SIZE)
+    };
+    let (Some(screen_width), Some(screen_height)) = (size(width, 2*BORDER_SIZE), size(height, 2*BORDER_SIZE + 1)) else {
+      let max = GameConfig { screen_width: MAX_SCREEN_SIZE, screen_height: MAX_SCREEN_SIZE, ..*self }.board();
+      return Err(format!("the board can be at most {}x{} cells", max.width, max.height));
+    };
+    self.screen_width = screen_width.max(SCREEN_WIDTH);
+    self.screen_height = screen_height.max(SCREEN_HEIGHT);
+    Ok(())
+  }
+
+  // Applies the command line on top of the saved settings and returns the
+  // state file to load, if one was given.
+  fn apply_args(&mut self, args: impl Iterator<Item = String>) -> Result<Option<String>, String> {
+    let mut load_path = None;
+    let Board { mut width, mut height, .. } = self.board();
+    let mut args = args;
+    while let Some(arg) = args.next() {
+      // Wins over the saved theme for this run.
+      if arg == "--high-contrast" {
+        self.theme = Theme::HighContrast;
+        continue;
+      }
+      if !["--width", "--height", "--seed", "--difficulty", "--load"].contains(&arg.as_str()) {
+        return Err(format!("unknown argument {}", arg));
+      }
+      let value = args.next().ok_or(format!("{} needs a value", arg))?;
+      let cells = || value.parse::<i32>().map_err(|_| format!("{} is not a number of cells", value));
+      match arg.as_str() {
+        "--width" => width = cells()?,
+        "--height" => height = cells()?,
+        "--seed" => {
+          let seed = u64::from_str_radix(value.trim_start_matches("0x"), 16)
+            .map_err(|_| format!("{} is not a hex seed", value))?;
+          self.seed = Some(seed);
+        }
+        "--difficulty" => {
+          let difficulty = Difficulty::from_arg(&value)
+            .ok_or(format!("{} is not a difficulty", value))?;
+          *self = GameConfig {
+            pixel_scale: self.pixel_scale,
+            seed: self.seed,
+            theme: self.theme,
+            background: self.background,
+            ..difficulty.config()
+          };
+        }
+        _ => load_path = Some(value)
+      }
+    }
+    self.set_board_size(width, height)?;
+    Ok(load_path)
+  }
+
+  fn settings_path(saves: &SaveFiles) -> Option<PathBuf> {
+    saves.path(".config/snek/settings.toml")
+  }
+
+  // Anything missing from the file keeps its default, and so does anything
+  // out of range, with a warning.
+  fn load(saves: &SaveFiles) -> Self {
+    let mut config: GameConfig = GameConfig::settings_path(saves)
+      .and_then(|path| fs::read_to_string(path).ok())
+      .and_then(|text| toml::from_str(&text).ok())
+      .unwrap_or_default();
+    for problem in config.reset_invalid() {
+      eprintln!("snek: settings.toml: {}", problem);
+    }
+    config
+  }
+
+  // Puts every setting the game can't run with back to its default, and
+  // says which ones those were.
+  fn reset_invalid(&mut self) -> Vec<String> {
+    let default = GameConfig::default();
+    let mut problems = Vec::new();
+    if !is_board_scale(self.pixel_scale) || SCREEN_WIDTH % self.pixel_scale != 0 {
+      problems.push(format!("pixel_scale {} is not one of the board sizes", self.pixel_scale));
+      self.pixel_scale = default.pixel_scale;
+    }
+    if self.ticks_per_move == 0 {
+      problems.push("ticks_per_move must be at least 1".to_string());
+      self.ticks_per_move = default.ticks_per_move;
+    }
+    if self.lives == 0 {
+      problems.push("lives must be at least 1".to_string());
+      self.lives = default.lives;
+    }
+    if !(1..=MAX_FOOD_COUNT).contains(&self.food_count) {
+      problems.push(format!("food_count {} is not between 1 and {}", self.food_count, MAX_FOOD_COUNT));
+      self.food_count = default.food_count;
+    }
+    if self.initial_length == 0 {
+      problems.push("initial_length must be at least 1".to_string());
+      self.initial_length = default.initial_length;
+    }
+    problems
+  }
+
+  fn save(&self, saves: &SaveFiles) {
+    let Some(path) = GameConfig::settings_path(saves) else { return };
+    if let (Some(dir), Ok(text)) = (path.parent(), toml::to_string(self)) {
+      let _ = fs::create_dir_all(dir);
+      let _ = fs::write(path, text);
+    }
+  }
+
+  // Kept to 32 bits so it fits the "Seed: XXXXXXXX" line on the dead screen.
+  fn game_seed(&self) -> u64 {
+    self.seed.unwrap_or_else(|| RandomNumberGenerator::new().next_u64() & 0xFFFF_FFFF)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Dir {
+  Static, // Only at the beginning.
+  Left,
+  Right,
+  Up,
+  Down
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub struct Cell {
+  pub x: i32,
+  pub y: i32
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Controls {
+  // Either the bound keys or the arrow keys, for a single player.
+  #[default]
+  Any,
+  // The bound keys, WASD unless keys.toml says otherwise.
+  Wasd,
+  Arrows
+}
+
+// Keys that can be named in keys.toml, by their VirtualKeyCode name.
+const BINDABLE_KEYS : [VirtualKeyCode; 44] = [
+  VirtualKeyCode::A, VirtualKeyCode::B, VirtualKeyCode::C, VirtualKeyCode::D,
+  VirtualKeyCode::E, VirtualKeyCode::F, VirtualKeyCode::G, VirtualKeyCode::H,
+  VirtualKeyCode::I, VirtualKeyCode::J, VirtualKeyCode::K, VirtualKeyCode::L,
+  VirtualKeyCode::M, VirtualKeyCode::N, VirtualKeyCode::O, VirtualKeyCode::P,
+  VirtualKeyCode::Q, VirtualKeyCode::R, VirtualKeyCode::S, VirtualKeyCode::T,
+  VirtualKeyCode::U, VirtualKeyCode::V, VirtualKeyCode::W, VirtualKeyCode::X,
+  VirtualKeyCode::Y, VirtualKeyCode::Z,
+  VirtualKeyCode::Key0, VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3,
+  VirtualKeyCode::Key4, VirtualKeyCode::Key5, VirtualKeyCode::Key6, VirtualKeyCode::Key7,
+  VirtualKeyCode::Key8, VirtualKeyCode::Key9,
+  VirtualKeyCode::Left, VirtualKeyCode::Right, VirtualKeyCode::Up, VirtualKeyCode::Down,
+  VirtualKeyCode::Space, VirtualKeyCode::Return, VirtualKeyCode::Tab, VirtualKeyCode::Back,
+];
+
+// The first player's keys. The second player is always on the arrows.
+#[derive(Debug, Clone, Copy)]
+struct KeyBindings {
+  pub left: VirtualKeyCode,
+  pub right: VirtualKeyCode,
+  pub up: VirtualKeyCode,
+  pub down: VirtualKeyCode,
+  pub pause: VirtualKeyCode
+}
+
+impl Default for KeyBindings {
+  fn default() -> Self {
+    KeyBindings {
+      left: VirtualKeyCode::A,
+      right: VirtualKeyCode::D,
+      up: VirtualKeyCode::W,
+      down: VirtualKeyCode::S,
+      pause: VirtualKeyCode::P
+    }
+  }
+}
+
+// keys.toml as written, any key left out keeps its default.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct KeyNames {
+  left: Option<String>,
+  right: Option<String>,
+  up: Option<String>,
+  down: Option<String>,
+  pause: Option<String>
+}
+
+impl KeyBindings {
+  // A missing or broken file just means the defaults.
+  fn load(saves: &SaveFiles) -> Self {
+    let names: KeyNames = saves.path(".config/snek/keys.toml")
+      .and_then(|path| fs::read_to_string(path).ok())
+      .and_then(|text| toml::from_str(&text).ok())
+      .unwrap_or_default();
+    let key = |name: Option<String>, default: VirtualKeyCode| {
+      name.and_then(|name| BINDABLE_KEYS.iter().copied().find(|key| format!("{:?}", key) == name))
+        .unwrap_or(default)
+    };
+    let defaults = KeyBindings::default();
+    KeyBindings {
+      left: key(names.left, defaults.left),
+      right: key(names.right, defaults.right),
+      up: key(names.up, defaults.up),
+      down: key(names.down, defaults.down),
+      pause: key(names.pause, defaults.pause)
+    }
+  }
+
+  fn dir_for(&self, key: VirtualKeyCode) -> Option<Dir> {
+    [(self.left, Dir::Left), (self.right, Dir::Right), (self.up, Dir::Up), (self.down, Dir::Down)]
+      .iter()
+      .find(|(bound, _)| *bound == key)
+      .map(|(_, dir)| *dir)
+  }
+}
+
+impl Controls {
+  fn dir_for(self, key: VirtualKeyCode, bindings: &KeyBindings) -> Option<Dir> {
+    let bound = self != Controls::Arrows;
+    let arrows = self != Controls::Wasd;
+    if let Some(dir) = bindings.dir_for(key).filter(|_| bound) {
+      return Some(dir);
+    }
+    match key {
+      VirtualKeyCode::Right if arrows => Some(Dir::Right),
+      VirtualKeyCode::Left if arrows => Some(Dir::Left),
+      VirtualKeyCode::Up if arrows => Some(Dir::Up),
+      VirtualKeyCode::Down if arrows => Some(Dir::Down),
+      _ => None
+    }
+  }
+}
+
+// The cells behind a snake's head, nearest the head first. The ring-tail
+// feature swaps in a ring buffer that is allocated once, which measured
+// slower, see src/ring_tail.rs.
+#[cfg(not(feature = "ring-tail"))]
+pub type Tail = VecDeque<Cell>;
+#[cfg(feature = "ring-tail")]
+pub type Tail = RingTail;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Player {
+  pub head: Cell,
+  pub tail: Tail,
+  pub dir: Dir,
+  // Turns pressed since the last move, applied one per move.
+  #[serde(skip)]
+  pub inputs: VecDeque<Dir>,
+  // Set while an invincibility power-up is running.
+  pub invincible: bool,
+  #[serde(skip)]
+  pub controls: Controls,
+  #[serde(skip)]
+  pub color: RGB,
+  // Moves made since the last change of direction.
+  pub straight_moves: u32,
+  // Cells the head has moved through over the whole game.
+  pub total_distance: u64,
+  // A clicked cell the snake is steering itself towards.
+  #[serde(skip)]
+  pub target: Option<Cell>
+}
+
+impl Dir {
+  fn from_name(name: &str) -> Option<Dir> {
+    match name {
+      "Left" => Some(Dir::Left),
+      "Right" => Some(Dir::Right),
+      "Up" => Some(Dir::Up),
+      "Down" => Some(Dir::Down),
+      _ => None
+    }
+  }
+
+  // Static has no opposite but itself.
+  fn opposite(self) -> Dir {
+    match self {
+      Dir::Left => Dir::Right,
+      Dir::Right => Dir::Left,
+      Dir::Up => Dir::Down,
+      Dir::Down => Dir::Up,
+      Dir::Static => Dir::Static
+    }
+  }
+
+  // Static is never opposite to anything, so the first move can go anywhere.
+  fn is_opposite(self, other: Dir) -> bool {
+    self != Dir::Static && self.opposite() == other
+  }
+}
+
+/// The same names a saved replay writes its turns as, and reads them back
+/// from.
+///
+/// # Examples
+///
+/// ```
+/// use snek::Dir;
+///
+/// assert_eq!(Dir::Left.to_string(), "Left");
+/// assert_eq!(format!("heading {}", Dir::Static), "heading Static");
+/// ```
+impl fmt::Display for Dir {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Dir::Left => "Left",
+      Dir::Right => "Right",
+      Dir::Up => "Up",
+      Dir::Down => "Down",
+      Dir::Static => "Static"
+    };
+    f.write_str(name)
+  }
+}
+
+/// An `(x, y)` pair, as the debug overlay shows the head.
+///
+/// # Examples
+///
+/// ```
+/// use snek::Cell;
+///
+/// assert_eq!(Cell::new(3, -1).to_string(), "(3, -1)");
+/// ```
+impl fmt::Display for Cell {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "({}, {})", self.x, self.y)
+  }
+}
+
+impl Cell {
+  pub const fn new(x: i32, y: i32) -> Self {
+    Cell{x, y}
+  }
+
+  fn render(&mut self, ctx: &mut BTerm, config: &GameConfig, color: RGB) {
+    self.render_glyph(ctx, config, color, to_cp437('@'));
+  }
+
+  fn render_colored(&mut self, color: (u8, u8, u8), ctx: &mut BTerm, config: &GameConfig) {
+    self.render(ctx, config, RGB::named(color));
+  }
+
+  fn render_head(&mut self, dir: &Dir, ctx: &mut BTerm, config: &GameConfig, color: RGB) {
+    let glyph = match dir {
+      Dir::Right => '►',
+      Dir::Left => '◄',
+      Dir::Up => '▲',
+      Dir::Down => '▼',
+      // No heading yet, so just show a face.
+      Dir::Static => '☺',
+    };
+    self.render_glyph(ctx, config, color, to_cp437(glyph));
+  }
+
+  fn render_glyph(&mut self, ctx: &mut BTerm, config: &GameConfig, color: RGB, glyph: FontCharType) {
+    let scale = config.pixel_scale;
+    let (x_pixel, y_pixel) = config.board().to_pixel(*self);
+    for dy in 0..scale {
+      for dx in 0..scale {
+        ctx.set(x_pixel+dx, y_pixel+dy, color, config.theme.colors().background, glyph);
+      }
+    }
+  }
+
+  fn right(curr: Cell) -> Cell {
+    Cell::new(curr.x+1, curr.y)
+  }
+
+  fn left(curr: Cell) -> Cell {
+    Cell::new(curr.x-1, curr.y)
+  }
+
+  fn up(curr: Cell) -> Cell {
+    Cell::new(curr.x, curr.y-1)
+  }
+
+  fn down(curr: Cell) -> Cell {
+    Cell::new(curr.x, curr.y+1)
+  }
+
+  fn step(curr: Cell, dir: Dir) -> Cell {
+    match dir {
+      Dir::Left => Cell::left(curr),
+      Dir::Right => Cell::right(curr),
+      Dir::Up => Cell::up(curr),
+      Dir::Down => Cell::down(curr),
+      Dir::Static => curr
+    }
+  }
+
+  // Steps between the two cells, ignoring walls and wrapping.
+  fn manhattan_distance(&self, other: &Cell) -> i32 {
+    (self.x - other.x).abs() + (self.y - other.y).abs()
+  }
+}
+
+impl Player {
+  fn new(x: i32, y: i32) -> Self {
+    Player::new_with_length(x, y, 2, Dir::Static)
+  }
+
+  // The tail trails back from the head against dir, off to the right for
+  // a snake that has not moved yet.
+  pub fn new_with_length(x: i32, y: i32, length: usize, dir: Dir) -> Self {
+    let behind = match dir {
+      Dir::Static => Dir::Right,
+      _ => dir.opposite()
+    };
+    let h = Cell::new(x, y);
+    let mut t = Tail::new();
+    let mut last = h;
+    for _ in 1..length {
+      last = Cell::step(last, behind);
+      t.push_back(last);
+    }
+    Player {
+      head: h,
+      tail: t,
+      dir,
+      inputs: VecDeque::with_capacity(INPUT_BUFFER_SIZE),
+      invincible: false,
+      controls: Controls::Any,
+      color: RGB::named(YELLOW),
+      straight_moves: 0,
+      total_distance: 0,
+      target: None
+    }
+  }
+
+  fn render_tail(&mut self, ctx: &mut BTerm, config: &GameConfig) {
+    let colors = self.tail_colors(RGB::named(config.theme.colors().snake_tail));
+    for (cell, color) in self.tail.iter_mut().zip(colors) {
+      cell.render_colored(color, ctx, config);
+    }
+  }
+
+  // A fade from the snake's own colour at the neck to tip at the end, one
+  // colour per tail cell.
+  fn tail_colors(&self, tip: RGB) -> Vec<(u8, u8, u8)> {
+    let head = (self.color.r, self.color.g, self.color.b);
+    let tip = (tip.r, tip.g, tip.b);
+    let len = self.tail.len().max(2) - 1;
+    (0..self.tail.len()).map(|index| {
+      let t = index as f32 / len as f32;
+      let channel = |from: f32, to: f32| ((from + (to - from) * t) * 255.0).round() as u8;
+      (channel(head.0, tip.0), channel(head.1, tip.1), channel(head.2, tip.2))
+    }).collect()
+  }
+
+  fn render(&mut self, ctx: &mut BTerm, config: &GameConfig, ticks: u64) {
+    // Always print the head of snek, pulsing while invincible.
+    let head_color = if self.invincible {
+      let phase = ((ticks % 20) as f32 / 10.0 - 1.0).abs();
+      RGB::named(GOLD).lerp(RGB::named(WHITE), phase)
+    } else {
+      self.color
+    };
+    let dir = self.dir;
+    self.head.render_head(&dir, ctx, config, head_color);
+    self.render_tail(ctx, config);
+    ctx.set_active_console(0);
+  }
+
+  fn update_direction(&mut self, key: Option<VirtualKeyCode>, bindings: &KeyBindings) {
+    if let Some(new_dir) = key.and_then(|key| self.controls.dir_for(key, bindings)) {
+      self.queue_turn(new_dir);
+    }
+  }
+
+  // Whether the turn was queued. It is compared against the last queued turn
+  // rather than the current heading, otherwise two quick presses could still
+  // turn the snake back on itself.
+  fn queue_turn(&mut self, new_dir: Dir) -> bool {
+    let last_dir = *self.inputs.back().unwrap_or(&self.dir);
+    let queued = self.inputs.len() < INPUT_BUFFER_SIZE
+      && new_dir != last_dir
+      && !new_dir.is_opposite(last_dir);
+    if queued {
+      self.inputs.push_back(new_dir);
+    }
+    queued
+  }
+
+  pub fn update_position(&mut self) {
+    // Reversals are already rejected in queue_turn.
+    match self.inputs.pop_front() {
+      Some(dir) if dir != self.dir => {
+        self.dir = dir;
+        self.straight_moves = 0;
+      }
+      // A snake that has not started moving is not going straight.
+      _ if self.dir != Dir::Static => self.straight_moves += 1,
+      _ => {}
+    }
+    let has_game_started = !matches!(self.dir, Dir::Static);
+    let prev_head = self.head;
+    if has_game_started {
+      self.head = Cell::step(self.head, self.dir);
+      self.total_distance += 1;
+      self.tail.push_front(prev_head);
+      self.tail.pop_back();
+    }
+  }
+
+  fn wrap_position(&mut self, config: &GameConfig) {
+    self.head = config.board().wrap(self.head);
+  }
+
+  // Wraps the head back onto the board where the mode allows it, and
+  // reports whether the head has gone through a solid wall.
+  fn check_walls(&mut self, board_mode: BoardMode, config: &GameConfig) -> bool {
+    if board_mode.wraps() {
+      self.wrap_position(config);
+      false
+    } else {
+      self.is_out_of_bounds(config)
+    }
+  }
+
+  // Drops the tail from the segment the head landed on, or the last
+  // segment if the head hit something else.
+  fn cut_at_head(&mut self) {
+    match self.tail.iter().position(|c| *c == self.head) {
+      Some(bitten) => self.tail.truncate(bitten),
+      None => self.shrink()
+    }
+  }
+
+  fn is_out_of_bounds(&mut self, config: &GameConfig) -> bool {
+    !config.board().contains(self.head)
+  }
+
+  // Must run after update_position so the cell vacated by the end of the
+  // tail this tick is no longer counted.
+  fn check_self_collision(&self) -> bool {
+    !self.invincible && self.tail_occupies(self.head)
+  }
+
+  // The head followed by every tail segment.
+  fn cells(&self) -> Vec<Cell> {
+    std::iter::once(self.head).chain(self.tail.iter().copied()).collect()
+  }
+
+  fn total_distance(&self) -> u64 {
+    self.total_distance
+  }
+
+  // Flood fills from the head through the cells that are free right now.
+  // Reaching fewer of them than there are tail cells means the snake can
+  // not fit anywhere it can get to.
+  fn is_trapped(&self, board: &BoardState) -> bool {
+    let mut seen = HashSet::from([self.head]);
+    let mut queue = VecDeque::from([self.head]);
+    while let Some(cell) = queue.pop_front() {
+      if seen.len() > self.tail.len() {
+        return false;
+      }
+      for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
+        let Some(next) = board.step(cell, dir) else { continue };
+        if !board.blocked.contains(&next) && !self.tail_occupies(next) && seen.insert(next) {
+          queue.push_back(next);
+        }
+      }
+    }
+    seen.len() <= self.tail.len()
+  }
+
+  // The first step of a breadth first search from the head, through cells
+  // that are on the board and neither blocked nor part of the tail.
+  fn shortest_path_to(&self, target: Cell, board: &BoardState) -> Option<Dir> {
+    let mut first_step = HashMap::from([(self.head, Dir::Static)]);
+    let mut queue = VecDeque::from([self.head]);
+    while let Some(cell) = queue.pop_front() {
+      if cell == target {
+        return first_step.get(&cell).copied().filter(|dir| *dir != Dir::Static);
+      }
+      for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
+        let Some(next) = board.step(cell, dir) else { continue };
+        if board.blocked.contains(&next) || self.tail_occupies(next) || first_step.contains_key(&next) {
+          continue;
+        }
+        let first = if cell == self.head { dir } else { first_step[&cell] };
+        first_step.insert(next, first);
+        queue.push_back(next);
+      }
+    }
+    None
+  }
+
+  // A copy of the snake as it would be after moving its head to next
+  // without growing.
+  fn moved_to(&self, next: Cell) -> Player {
+    let mut moved = self.clone();
+    moved.tail.push_front(moved.head);
+    moved.tail.pop_back();
+    moved.head = next;
+    moved
+  }
+
+  pub fn occupies(&self, cell: Cell) -> bool {
+    self.head == cell || self.tail_occupies(cell)
+  }
+
+  // Like occupies, but without the head.
+  pub fn tail_occupies(&self, cell: Cell) -> bool {
+    self.tail.contains(&cell)
+  }
+
+  // Leaves out the tail end, which moves away as the head moves in.
+  fn body_contains(&self, cell: Cell) -> bool {
+    let body_len = self.tail.len().saturating_sub(1);
+    self.tail.iter().take(body_len).any(|c| *c == cell)
+  }
+
+  // Whether a step in dir keeps the head on the board and off the body.
+  // Anything else in the way is up to the caller.
+  fn can_move_in(&self, dir: Dir, board: &Board) -> bool {
+    let next = Cell::step(self.head, dir);
+    dir != Dir::Static && board.contains(next) && !self.body_contains(next)
+  }
+
+  // Head plus tail.
+  fn len(&self) -> usize {
+    1 + self.tail.len()
+  }
+
+  fn shrink(&mut self) {
+    self.tail.pop_back();
+  }
+
+  // Called once the head is already on the food. The new segment starts on
+  // top of the tail end and separates on the next move, so every segment
+  // keeps following the one in front of it.
+  pub fn grow(&mut self) {
+    let end = *self.tail.back().unwrap_or(&self.head);
+    self.tail.push_back(end);
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum FoodKind {
+  Normal,
+  // Takes a segment off the snake instead of adding one.
+  Poison
+}
+
+// Everything a snake can eat, for working out what it scores.
+#[derive(Clone, Copy, PartialEq)]
+enum Meal {
+  Food(FoodKind),
+  Mobile,
+  Bonus
+}
+
+// A computer controlled snake that heads greedily for the closest food.
+struct AiPlayer {
+  pub player: Player
+}
+
+impl AiPlayer {
+  fn new(x: i32, y: i32, length: usize) -> Self {
+    let mut player = Player::new_with_length(x, y, length, Dir::Static);
+    player.color = RGB::named(PURPLE);
+    AiPlayer { player }
+  }
+
+  // Follows the shortest path to the nearest target it can reach, unless
+  // its first step leaves the snake trapped. Otherwise it picks the safe
+  // direction whose next cell is closest to any target. Moves that would
+  // trap the snake only win when nothing else is safe, and the current
+  // heading is kept when every move is fatal.
+  fn choose_direction(&mut self, targets: &[Cell], board: &BoardState) {
+    let snake = &self.player;
+    let mut by_distance = targets.to_vec();
+    by_distance.sort_by_key(|target| snake.head.manhattan_distance(target));
+    if let Some(dir) = by_distance.iter().find_map(|target| snake.shortest_path_to(*target, board)) {
+      let trapped = board.step(snake.head, dir).is_some_and(|next| snake.moved_to(next).is_trapped(board));
+      if !trapped {
+        self.player.dir = dir;
+        return;
+      }
+    }
+    let mut best: Option<(bool, i32, Dir)> = None;
+    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
+      if dir.is_opposite(snake.dir) {
+        continue;
+      }
+      let Some(next) = board.step(snake.head, dir) else { continue };
+      if board.blocked.contains(&next) || snake.body_contains(next) {
+        continue;
+      }
+      let trapped = snake.moved_to(next).is_trapped(board);
+      let score = targets.iter().map(|t| next.manhattan_distance(t)).min().unwrap_or(0);
+      if best.is_none_or(|(best_trapped, best_score, _)| (trapped, score) < (best_trapped, best_score)) {
+        best = Some((trapped, score, dir));
+      }
+    }
+    if let Some((_, _, dir)) = best {
+      self.player.dir = dir;
+    }
+  }
+}
+
+// Turns from the d-pad or left stick of any connected gamepad. Built
+// without the gamepad feature, or with no gamepad support on the system,
+// it never reports any.
+struct Gamepad {
+  #[cfg(feature = "gamepad")]
+  gilrs: Option<gilrs::Gilrs>
+}
+
+impl Gamepad {
+  fn new() -> Self {
+    Gamepad {
+      #[cfg(feature = "gamepad")]
+      gilrs: gilrs::Gilrs::new().ok()
+    }
+  }
+
+  // The latest turn since the last poll.
+  #[cfg(feature = "gamepad")]
+  fn poll(&mut self) -> Option<Dir> {
+    use gilrs::{Axis, Button, Event, EventType};
+    // How far the stick has to be pushed to count as a turn.
+    const STICK_THRESHOLD : f32 = 0.5;
+    let gilrs = self.gilrs.as_mut()?;
+    let mut turn = None;
+    while let Some(Event { event, .. }) = gilrs.next_event() {
+      turn = match event {
+        EventType::ButtonPressed(Button::DPadUp, _) => Some(Dir::Up),
+        EventType::ButtonPressed(Button::DPadDown, _) => Some(Dir::Down),
+        EventType::ButtonPressed(Button::DPadLeft, _) => Some(Dir::Left),
+        EventType::ButtonPressed(Button::DPadRight, _) => Some(Dir::Right),
+        EventType::AxisChanged(Axis::LeftStickX, value, _) if value > STICK_THRESHOLD => Some(Dir::Right),
+        EventType::AxisChanged(Axis::LeftStickX, value, _) if value < -STICK_THRESHOLD => Some(Dir::Left),
+        // Up is positive on the stick.
+        EventType::AxisChanged(Axis::LeftStickY, value, _) if value > STICK_THRESHOLD => Some(Dir::Up),
+        EventType::AxisChanged(Axis::LeftStickY, value, _) if value < -STICK_THRESHOLD => Some(Dir::Down),
+        _ => turn
+      };
+    }
+    turn
+  }
+
+  #[cfg(not(feature = "gamepad"))]
+  fn poll(&mut self) -> Option<Dir> {
+    None
+  }
+}
+
+// The cells the snakes play on and where each one is drawn.
+#[derive(Debug, Clone, Copy)]
+struct Board {
+  pub width: i32,
+  pub height: i32,
+  pub pixel_scale: i32
+}
+
+impl Board {
+  fn contains(&self, cell: Cell) -> bool {
+    cell.x >= 0 && cell.x < self.width && cell.y >= 0 && cell.y < self.height
+  }
+
+  fn cells(self) -> impl Iterator<Item = Cell> {
+    (0..self.height).flat_map(move |y| (0..self.width).map(move |x| Cell::new(x, y)))
+  }
+
+  fn random_cell(&self, rng: &mut RandomNumberGenerator) -> Cell {
+    Cell::new(rng.range(0, self.width), rng.range(0, self.height))
+  }
+
+  fn wrap(&self, cell: Cell) -> Cell {
+    Cell::new(cell.x.rem_euclid(self.width), cell.y.rem_euclid(self.height))
+  }
+
+  // Each cell of a level layout becomes a square block, as big as still
+  // fits the layout on the board, and the whole is centred.
+  fn fit_layout(&self, layout: &[Cell]) -> Vec<Cell> {
+    let scale = (self.width.min(self.height) / LEVEL_SIZE).max(1);
+    let offset = |size: i32| ((size - LEVEL_SIZE*scale) / 2).max(0);
+    let (dx, dy) = (offset(self.width), offset(self.height));
+    layout.iter()
+      .flat_map(|cell| (0..scale*scale).map(move |i| {
+        Cell::new(dx + cell.x*scale + i % scale, dy + cell.y*scale + i / scale)
+      }))
+      .collect()
+  }
+
+  // The console position of the top left character of the cell.
+  fn to_pixel(self, cell: Cell) -> (i32, i32) {
+    (BORDER_SIZE + self.pixel_scale*cell.x, BORDER_SIZE + self.pixel_scale*cell.y)
+  }
+
+  // The cell drawn at a console position, if it is on the board.
+  fn cell_at(&self, (x, y): (i32, i32)) -> Option<Cell> {
+    if x < BORDER_SIZE || y < BORDER_SIZE {
+      return None;
+    }
+    let cell = Cell::new((x - BORDER_SIZE) / self.pixel_scale, (y - BORDER_SIZE) / self.pixel_scale);
+    self.contains(cell).then_some(cell)
+  }
+}
+
+// Everything a snake has to steer around apart from its own tail.
+struct BoardState {
+  pub board: Board,
+  pub wraps: bool,
+  pub blocked: HashSet<Cell>
+}
+
+impl BoardState {
+  fn new(config: &GameConfig, board_mode: BoardMode, blocked: impl IntoIterator<Item = Cell>) -> Self {
+    BoardState {
+      board: config.board(),
+      wraps: board_mode.wraps(),
+      blocked: blocked.into_iter().collect()
+    }
+  }
+
+  // Where a step from cell lands, or None if that is off the board.
+  fn step(&self, cell: Cell, dir: Dir) -> Option<Cell> {
+    let mut next = Cell::step(cell, dir);
+    if self.wraps {
+      next = self.board.wrap(next);
+    }
+    self.board.contains(next).then_some(next)
+  }
+}
+
+// Shown for the first half of each blink period. The period drops in
+// steps as the item runs out, so the blinking speeds up near the end.
+fn blink_visible(ticks_left: u64) -> bool {
+  let period = match ticks_left {
+    t if t > 2 * FOOD_BLINK_TICKS => FOOD_BLINK_TICKS,
+    t if t > FOOD_BLINK_TICKS => FOOD_BLINK_TICKS / 2,
+    _ => MIN_FOOD_BLINK_TICKS
+  };
+  ticks_left % period < period / 2
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Food {
+  pub pos: Cell,
+  pub kind: FoodKind,
+  // The tick it last moved to where it is.
+  #[serde(default)]
+  pub spawn_tick: u64,
+  #[serde(skip, default = "RandomNumberGenerator::new")]
+  pos_gen: RandomNumberGenerator
+}
+
+impl Food {
+  // Normal food at exactly x, y, with a fresh generator for where it goes
+  // after that.
+  fn new_at(x: i32, y: i32) -> Self {
+    Food {
+      pos: Cell::new(x, y),
+      kind: FoodKind::Normal,
+      spawn_tick: 0,
+      pos_gen: RandomNumberGenerator::new()
+    }
+  }
+
+  fn with_seed(seed: u64, snake: &Player, blocked: &[Cell], config: &GameConfig) -> Self {
+    let mut food = Food { pos_gen: RandomNumberGenerator::seeded(seed), ..Food::new_at(0, 0) };
+    food.respawn(snake, blocked, config);
+    food
+  }
+
+  fn render(&mut self, ctx: &mut BTerm, config: &GameConfig, ticks: u64) {
+    if !blink_visible(self.ticks_left(ticks)) {
+      return;
+    }
+    match self.kind {
+      FoodKind::Normal => {
+        let colors = config.theme.colors();
+        self.pos.render_glyph(ctx, config, RGB::named(colors.food), to_cp437(colors.food_glyph));
+      }
+      FoodKind::Poison => self.pos.render_glyph(ctx, config, RGB::named(RED), to_cp437('x')),
+    }
+    ctx.set_active_console(0);
+  }
+
+  fn ticks_left(&self, ticks: u64) -> u64 {
+    FOOD_LIFETIME.saturating_sub(ticks.saturating_sub(self.spawn_tick))
+  }
+
+  // blocked holds any other cells the food must not land on.
+  fn respawn(&mut self, snake: &Player, blocked: &[Cell], config: &GameConfig) {
+    let is_free = |cell: &Cell| {
+      !snake.occupies(*cell) && !blocked.contains(cell)
+        && cell.manhattan_distance(&snake.head) >= FOOD_MIN_DISTANCE
+    };
+    if let Some(cell) = random_free_cell(&mut self.pos_gen, config, is_free) {
+      self.pos = cell;
+    }
+    self.kind = if self.pos_gen.range(0, POISON_ODDS) == 0 {
+      FoodKind::Poison
+    } else {
+      FoodKind::Normal
+    };
+  }
+}
+
+// Food that keeps walking in a straight line and turns back at walls.
+struct MobileFood {
+  pub pos: Cell,
+  pub dir: Dir,
+  pub move_interval: u64
+}
+
+impl MobileFood {
+  fn new(pos: Cell, rng: &mut RandomNumberGenerator) -> Self {
+    let dir = match rng.range(0, 4) {
+      0 => Dir::Left,
+      1 => Dir::Right,
+      2 => Dir::Up,
+      _ => Dir::Down
+    };
+    MobileFood { pos, dir, move_interval: MOBILE_FOOD_INTERVAL }
+  }
+
+  // Stays put for a step when it is boxed in from both sides.
+  fn update(&mut self, blocked: &[Cell], config: &GameConfig) {
+    let can_enter = |cell: Cell| config.board().contains(cell) && !blocked.contains(&cell);
+    let ahead = Cell::step(self.pos, self.dir);
+    if can_enter(ahead) {
+      self.pos = ahead;
+      return;
+    }
+    self.dir = self.dir.opposite();
+    let behind = Cell::step(self.pos, self.dir);
+    if can_enter(behind) {
+      self.pos = behind;
+    }
+  }
+
+  fn render(&mut self, ctx: &mut BTerm, config: &GameConfig) {
+    self.pos.render_glyph(ctx, config, RGB::named(config.theme.colors().mobile_food), to_cp437('%'));
+  }
+}
+
+// A bar of wall sliding across the board, turning back at the edges and
+// at anything else in its way.
+struct MovingWall {
+  pub cells: Vec<Cell>,
+  pub dir: Dir,
+  pub speed: u64
+}
+
+impl MovingWall {
+  fn new(x: i32, y: i32, dir: Dir) -> Self {
+    MovingWall {
+      cells: (y..y + MOVING_WALL_LENGTH).map(|row| Cell::new(x, row)).collect(),
+      dir,
+      speed: MOVING_WALL_SPEED
+    }
+  }
+
+  // Returns whether it moved this tick.
+  fn update(&mut self, ticks: u64, blocked: &[Cell], config: &GameConfig) -> bool {
+    if !ticks.is_multiple_of(self.speed) {
+      return false;
+    }
+    for _ in 0..2 {
+      let next: Vec<Cell> = self.cells.iter().map(|cell| Cell::step(*cell, self.dir)).collect();
+      let can_enter = |cell: &Cell| config.board().contains(*cell) && !blocked.contains(cell);
+      if next.iter().all(can_enter) {
+        self.cells = next;
+        return true;
+      }
+      self.dir = self.dir.opposite();
+    }
+    false
+  }
+
+  fn render(&mut self, ctx: &mut BTerm, config: &GameConfig) {
+    for cell in self.cells.iter_mut() {
+      cell.render_glyph(ctx, config, RGB::named(DARK_RED), to_cp437('|'));
+    }
+  }
+}
+
+// Entering either end puts the head on the other end, still heading the
+// same way.
+#[derive(Clone, Copy)]
+struct Portal {
+  pub a: Cell,
+  pub b: Cell
+}
+
+impl Portal {
+  fn exit_for(&self, cell: Cell) -> Option<Cell> {
+    if cell == self.a {
+      Some(self.b)
+    } else if cell == self.b {
+      Some(self.a)
+    } else {
+      None
+    }
+  }
+
+  fn render(&mut self, ctx: &mut BTerm, config: &GameConfig) {
+    self.a.render_glyph(ctx, config, RGB::named(MAGENTA), to_cp437('O'));
+    self.b.render_glyph(ctx, config, RGB::named(MAGENTA), to_cp437('O'));
+  }
+}
+
+struct BonusFood {
+  pub pos: Cell,
+  pub ticks_remaining: u64
+}
+
+impl BonusFood {
+  fn new(pos: Cell) -> Self {
+    BonusFood {
+      pos,
+      ticks_remaining: BONUS_LIFETIME
+    }
+  }
+
+  fn render(&mut self, ctx: &mut BTerm, config: &GameConfig) {
+    if !blink_visible(self.ticks_remaining) {
+      return;
+    }
+    self.pos.render_glyph(ctx, config, RGB::named(GOLD), to_cp437('$'));
+  }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PowerUpKind {
+  // Halves the move interval while active.
+  SpeedBoost,
+  // Doubles the move interval while active.
+  SlowMotion,
+  // Lets the head pass through the tail while active.
+  Invincible,
+  // Takes effect at once, moving the head to a free cell and leaving the
+  // tail where it is.
+  Teleport
+}
+
+impl PowerUpKind {
+  fn random(rng: &mut RandomNumberGenerator) -> Self {
+    match rng.range(0, 4) {
+      0 => PowerUpKind::SpeedBoost,
+      1 => PowerUpKind::SlowMotion,
+      2 => PowerUpKind::Invincible,
+      _ => PowerUpKind::Teleport
+    }
+  }
+
+  fn duration(self) -> u64 {
+    match self {
+      PowerUpKind::Invincible => INVINCIBLE_DURATION,
+      _ => POWER_UP_DURATION
+    }
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      PowerUpKind::SpeedBoost => "Fast",
+      PowerUpKind::SlowMotion => "Slow",
+      PowerUpKind::Invincible => "Invincible",
+      PowerUpKind::Teleport => "Teleport"
+    }
+  }
+
+  fn render(self, pos: &mut Cell, ctx: &mut BTerm, config: &GameConfig) {
+    match self {
+      PowerUpKind::SpeedBoost => pos.render_glyph(ctx, config, RGB::named(CYAN), to_cp437('>')),
+      PowerUpKind::SlowMotion => pos.render_glyph(ctx, config, RGB::named(BLUE), to_cp437('<')),
+      PowerUpKind::Invincible => pos.render_glyph(ctx, config, RGB::named(WHITE), to_cp437('*')),
+      PowerUpKind::Teleport => pos.render_glyph(ctx, config, RGB::named(MAGENTA), to_cp437('?')),
+    }
+  }
+}
+
+// Returns None only when there is no free cell left on the board.
+fn random_free_cell(rng: &mut RandomNumberGenerator, config: &GameConfig, is_free: impl Fn(&Cell) -> bool) -> Option<Cell> {
+  let board = config.board();
+  for _ in 0..MAX_SPAWN_ATTEMPTS {
+    let cell = board.random_cell(rng);
+    if is_free(&cell) {
+      return Some(cell);
+    }
+  }
+  // The board is nearly full, take the first free cell if there is one.
+  board.cells().find(|cell| is_free(cell))
+}
+
+// Only the pixel scales on the settings screen are allowed, each of which
+// divides the default screen.
+fn is_board_scale(scale: i32) -> bool {
+  BOARD_SETTINGS.iter().any(|(_, choice)| *choice == scale)
+}
+
+// The name of the choice matching the current value, if any does.
+fn setting_name<T: PartialEq>(choices: &[(&'static str, T)], value: T) -> &'static str {
+  choices.iter().find(|(_, choice)| *choice == value).map_or("Custom", |(name, _)| name)
+}
+
+// Moves one choice forwards or backwards, wrapping around at either end.
+fn cycle_setting<T: PartialEq + Copy>(choices: &[(&str, T)], value: T, forwards: bool) -> T {
+  let len = choices.len();
+  let next = match choices.iter().position(|(_, choice)| *choice == value) {
+    Some(index) if forwards => (index + 1) % len,
+    Some(index) => (index + len - 1) % len,
+    None => 0
+  };
+  choices[next].1
+}
+
+// Where everything the game keeps between runs is read from and written
+// to, the home directory for the real game. With no directory nothing is
+// loaded and nothing is saved, which is what the tests use.
+#[derive(Debug, Clone)]
+pub struct SaveFiles {
+  dir: Option<PathBuf>
+}
+
+impl SaveFiles {
+  pub fn home() -> Self {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+    SaveFiles { dir: home.map(PathBuf::from) }
+  }
+
+  pub fn in_dir(dir: impl Into<PathBuf>) -> Self {
+    SaveFiles { dir: Some(dir.into()) }
+  }
+
+  pub fn none() -> Self {
+    SaveFiles { dir: None }
+  }
+
+  fn path(&self, name: &str) -> Option<PathBuf> {
+    self.dir.as_ref().map(|dir| dir.join(name))
+  }
+}
+
+fn name_path(saves: &SaveFiles) -> Option<PathBuf> {
+  saves.path(".snek_name")
+}
+
+fn load_name(saves: &SaveFiles) -> String {
+  name_path(saves)
+    .and_then(|path| fs::read_to_string(path).ok())
+    .map(|text| text.trim().chars().take(MAX_NAME_LEN).collect())
+    .unwrap_or_default()
+}
+
+fn save_name(saves: &SaveFiles, name: &str) {
+  if let Some(path) = name_path(saves) {
+    let _ = fs::write(path, name);
+  }
+}
+
+// The letter or digit a key types, going by its VirtualKeyCode name.
+fn name_char(key: VirtualKeyCode) -> Option<char> {
+  let name = format!("{:?}", key);
+  let mut chars = name.strip_prefix("Key").unwrap_or(&name).chars();
+  match (chars.next(), chars.next()) {
+    (Some(c), None) if c.is_ascii_alphanumeric() => Some(c),
+    _ => None
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn unix_secs() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+// SystemTime::now panics in the browser, so ask JavaScript for the time.
+#[cfg(target_arch = "wasm32")]
+fn unix_secs() -> u64 {
+  (js_sys::Date::now() / 1000.0) as u64
+}
+
+// Today's date as YYYY-MM-DD in UTC, converted from the days since the
+// epoch with Howard Hinnant's civil_from_days.
+fn today() -> String {
+  let secs = unix_secs();
+  let z = (secs / 86400) as i64 + 719468;
+  let era = z.div_euclid(146097);
+  let day_of_era = z.rem_euclid(146097);
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+  let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+  let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+  format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LeaderboardEntry {
+  pub name: String,
+  pub score: i32,
+  pub date: String
+}
+
+// The best LEADERBOARD_SIZE scores, highest first, with one entry per name.
+// Kept as JSON in ~/.config/snek/leaderboard.json.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Leaderboard(Vec<LeaderboardEntry>);
+
+impl Leaderboard {
+  fn path(saves: &SaveFiles) -> Option<PathBuf> {
+    saves.path(".config/snek/leaderboard.json")
+  }
+
+  fn load(saves: &SaveFiles) -> Self {
+    Leaderboard::path(saves)
+      .and_then(|path| fs::read_to_string(path).ok())
+      .and_then(|text| serde_json::from_str(&text).ok())
+      .unwrap_or_default()
+  }
+
+  fn save(&self, saves: &SaveFiles) {
+    let Some(path) = Leaderboard::path(saves) else { return };
+    if let (Some(dir), Ok(text)) = (path.parent(), serde_json::to_string_pretty(self)) {
+      let _ = fs::create_dir_all(dir);
+      let _ = fs::write(path, text);
+    }
+  }
+
+  // Takes the place of the player's own entry if it beats it, otherwise
+  // goes in wherever it ranks. Returns whether the board changed.
+  fn try_insert(&mut self, entry: LeaderboardEntry) -> bool {
+    let entries = &mut self.0;
+    if let Some(own) = entries.iter().position(|other| other.name == entry.name) {
+      if entries[own].score >= entry.score {
+        return false;
+      }
+      entries.remove(own);
+    }
+    let rank = entries.iter().position(|other| other.score < entry.score).unwrap_or(entries.len());
+    if rank >= LEADERBOARD_SIZE {
+      return false;
+    }
+    entries.insert(rank, entry);
+    entries.truncate(LEADERBOARD_SIZE);
+    true
+  }
+}
+
+fn ghost_path(saves: &SaveFiles) -> Option<PathBuf> {
+  saves.path(".snek_ghost_path")
+}
+
+// One "tick x y" line per move of the best run's head. A missing or
+// damaged file just means there is no ghost.
+fn load_ghost(saves: &SaveFiles) -> Vec<(u64, Cell)> {
+  let text = ghost_path(saves)
+    .and_then(|path| fs::read_to_string(path).ok())
+    .unwrap_or_default();
+  text.lines()
+    .filter_map(|line| {
+      let mut fields = line.split_whitespace().map(|field| field.parse::<i64>().ok());
+      let (tick, x, y) = (fields.next()??, fields.next()??, fields.next()??);
+      Some((tick as u64, Cell::new(x as i32, y as i32)))
+    })
+    .collect()
+}
+
+fn save_ghost(saves: &SaveFiles, path: &[(u64, Cell)]) {
+  if let Some(file) = ghost_path(saves) {
+    let text: String = path.iter()
+      .map(|(tick, cell)| format!("{} {} {}\n", tick, cell.x, cell.y))
+      .collect();
+    let _ = fs::write(file, text);
+  }
+}
+
+fn high_score_path(saves: &SaveFiles) -> Option<PathBuf> {
+  saves.path(".snek_highscore")
+}
+
+// One "difficulty score" line per difficulty. A file from before there
+// were difficulties holds just the one number, the Normal record. A
+// missing or unreadable file just means there is no record yet.
+fn load_high_scores(saves: &SaveFiles) -> HashMap<Difficulty, i32> {
+  let text = high_score_path(saves)
+    .and_then(|path| fs::read_to_string(path).ok())
+    .unwrap_or_default();
+  let mut scores = HashMap::new();
+  for line in text.lines().map(str::trim) {
+    let record = match line.split_once(' ') {
+      Some((name, score)) => Difficulty::from_name(name).zip(score.trim().parse().ok()),
+      None => line.parse().ok().map(|score| (Difficulty::Normal, score))
+    };
+    if let Some((difficulty, score)) = record {
+      scores.insert(difficulty, score);
+    }
+  }
+  scores
+}
+
+fn save_high_scores(saves: &SaveFiles, scores: &HashMap<Difficulty, i32>) {
+  if let Some(path) = high_score_path(saves) {
+    let text: String = DIFFICULTY_SETTINGS.iter()
+      .filter_map(|(name, difficulty)| scores.get(difficulty).map(|score| format!("{} {}\n", name, score)))
+      .collect();
+    // Losing the record is not worth crashing the game over.
+    let _ = fs::write(path, text);
+  }
+}
+
+// Totals over every game played on this machine, kept in ~/.snek_stats.
+#[derive(Debug, Clone, Copy, Default)]
+struct Stats {
+  pub games_played: u32,
+  pub total_food_eaten: u32,
+  pub total_distance: u64,
+  pub best_score: i32
+}
+
+impl Stats {
+  // Stored as one "name value" pair per line. Anything missing or
+  // unreadable starts from zero, like the high score.
+  fn load(saves: &SaveFiles) -> Self {
+    let mut stats = Stats::default();
+    let text = saves.path(".snek_stats").and_then(|path| fs::read_to_string(path).ok());
+    for line in text.unwrap_or_default().lines() {
+      let Some((name, value)) = line.split_once(' ') else { continue };
+      let value = value.trim();
+      match name {
+        "games_played" => stats.games_played = value.parse().unwrap_or(0),
+        "total_food_eaten" => stats.total_food_eaten = value.parse().unwrap_or(0),
+        "total_distance" => stats.total_distance = value.parse().unwrap_or(0),
+        "best_score" => stats.best_score = value.parse().unwrap_or(0),
+        _ => {}
+      }
+    }
+    stats
+  }
+
+  fn save(&self, saves: &SaveFiles) {
+    if let Some(path) = saves.path(".snek_stats") {
+      let text = format!(
+        "games_played {}\ntotal_food_eaten {}\ntotal_distance {}\nbest_score {}\n",
+        self.games_played, self.total_food_eaten, self.total_distance, self.best_score);
+      let _ = fs::write(path, text);
+    }
+  }
+}
+
+// Everything needed to play the last game again: the board it was played
+// on and each turn the player queued, keyed by the tick it was pressed on.
+struct Replay {
+  pub seed: u64,
+  pub board_mode: BoardMode,
+  pub play_mode: PlayMode,
+  pub difficulty: Difficulty,
+  pub ticks_per_move: u64,
+  pub pixel_scale: i32,
+  // The board in cells. The window can't be resized, so a replay only
+  // plays in a window that fits the same board.
+  pub width: i32,
+  pub height: i32,
+  pub lives: u8,
+  pub inputs: Vec<(u64, Dir)>,
+  // Ticks a hint was asked for on, since hints cost points.
+  pub hints: Vec<u64>
+}
+
+impl Replay {
+  fn path(saves: &SaveFiles) -> Option<PathBuf> {
+    saves.path(".snek_last_replay")
+  }
+
+  // A header of "name value" lines followed by one "tick direction" line
+  // per turn, or "tick hint" for a hint.
+  fn load(saves: &SaveFiles) -> Option<Self> {
+    let text = Replay::path(saves).and_then(|path| fs::read_to_string(path).ok())?;
+    let mut lines = text.lines();
+    let mut header = |name: &str| lines.next()?.strip_prefix(name)?.strip_prefix(' ').map(str::to_string);
+    let seed = u64::from_str_radix(&header("seed")?, 16).ok()?;
+    let board_mode = BoardMode::from_name(&header("board")?)?;
+    let play_mode = PlayMode::from_name(&header("mode")?)?;
+    let difficulty = Difficulty::from_name(&header("difficulty")?)?;
+    let ticks_per_move = header("speed")?.parse().ok()?;
+    let pixel_scale = header("scale")?.parse().ok().filter(|scale| is_board_scale(*scale))?;
+    let width = header("width")?.parse().ok()?;
+    let height = header("height")?.parse().ok()?;
+    let lives = header("lives")?.parse().ok()?;
+    let events: Vec<(u64, &str)> = lines
+      .filter_map(|line| {
+        let (tick, event) = line.split_once(' ')?;
+        Some((tick.parse().ok()?, event.trim()))
+      })
+      .collect();
+    let inputs = events.iter()
+      .filter_map(|(tick, event)| Some((*tick, Dir::from_name(event)?)))
+      .collect();
+    let hints = events.iter()
+      .filter(|(_, event)| *event == "hint")
+      .map(|(tick, _)| *tick)
+      .collect();
+    Some(Replay { seed, board_mode, play_mode, difficulty, ticks_per_move, pixel_scale, width, height, lives, inputs, hints })
+  }
+
+  fn save(&self, saves: &SaveFiles) {
+    let Some(path) = Replay::path(saves) else { return };
+    let mut text = format!("seed {:X}\nboard {}\nmode {}\ndifficulty {}\nspeed {}\nscale {}\nwidth {}\nheight {}\nlives {}\n",
+      self.seed, self.board_mode.name(), self.play_mode.name(), self.difficulty.name(),
+      self.ticks_per_move, self.pixel_scale, self.width, self.height, self.lives);
+    for (tick, dir) in self.inputs.iter() {
+      text.push_str(&format!("{} {}\n", tick, dir));
+    }
+    for tick in self.hints.iter() {
+      text.push_str(&format!("{} hint\n", tick));
+    }
+    let _ = fs::write(path, text);
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Achievement {
+  FirstFood,
+  Length10,
+  Score100,
+  // Thirty moves in a row without turning.
+  NoTurns,
+  SurvivedLevel5
+}
+
+impl Achievement {
+  const ALL : [Achievement; 5] = [
+    Achievement::FirstFood,
+    Achievement::Length10,
+    Achievement::Score100,
+    Achievement::NoTurns,
+    Achievement::SurvivedLevel5,
+  ];
+
+  fn name(self) -> &'static str {
+    match self {
+      Achievement::FirstFood => "First Food",
+      Achievement::Length10 => "Length 10",
+      Achievement::Score100 => "Score 100",
+      Achievement::NoTurns => "No Turns",
+      Achievement::SurvivedLevel5 => "Survived Level 5",
+    }
+  }
+}
+
+fn achievements_path(saves: &SaveFiles) -> Option<PathBuf> {
+  saves.path(".snek_achievements")
+}
+
+// Stored as one achievement name per line.
+fn load_achievements(saves: &SaveFiles) -> HashSet<Achievement> {
+  let text = achievements_path(saves)
+    .and_then(|path| fs::read_to_string(path).ok())
+    .unwrap_or_default();
+  Achievement::ALL.iter()
+    .copied()
+    .filter(|achievement| text.lines().any(|line| line.trim() == achievement.name()))
+    .collect()
+}
+
+fn save_achievements(saves: &SaveFiles, achievements: &HashSet<Achievement>) {
+  if let Some(path) = achievements_path(saves) {
+    let names: Vec<&str> = achievements.iter().map(|achievement| achievement.name()).collect();
+    let _ = fs::write(path, names.join("\n"));
+  }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BoardMode {
+  Classic,
+  // Leaving one edge brings the snake back in on the opposite one.
+  Wrapping,
+  // Wraps like Wrapping, and crashing costs tail instead of a life.
+  Zen,
+  // Every level is a freshly generated maze instead of a fixed layout.
+  Maze
+}
+
+impl BoardMode {
+  fn from_name(name: &str) -> Option<Self> {
+    let mut mode = BoardMode::Classic;
+    while mode.name() != name {
+      mode = mode.next();
+      if mode == BoardMode::Classic {
+        return None;
+      }
+    }
+    Some(mode)
+  }
+
+  fn next(self) -> Self {
+    match self {
+      BoardMode::Classic => BoardMode::Wrapping,
+      BoardMode::Wrapping => BoardMode::Zen,
+      BoardMode::Zen => BoardMode::Maze,
+      BoardMode::Maze => BoardMode::Classic
+    }
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      BoardMode::Classic => "Classic",
+      BoardMode::Wrapping => "Wrapping",
+      BoardMode::Zen => "Zen",
+      BoardMode::Maze => "Maze"
+    }
+  }
+
+  fn wraps(self) -> bool {
+    matches!(self, BoardMode::Wrapping | BoardMode::Zen)
+  }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PlayMode {
+  Single,
+  // A second snake on the arrow keys shares the board and the food.
+  TwoPlayer,
+  // A computer snake competes for the food.
+  VsAI,
+  // Survive until the clock runs out for a bonus.
+  TimeAttack,
+  // The snake keeps growing on its own, food only scores.
+  Survival,
+  // The food has to be eaten in colour order.
+  Challenge,
+  // The snake steers itself to the food until a turn is pressed, and goes
+  // back to doing so once the player leaves it alone for a while.
+  Training
+}
+
+impl PlayMode {
+  fn from_name(name: &str) -> Option<Self> {
+    let mut mode = PlayMode::Single;
+    while mode.name() != name {
+      mode = mode.next();
+      if mode == PlayMode::Single {
+        return None;
+      }
+    }
+    Some(mode)
+  }
+
+  fn next(self) -> Self {
+    match self {
+      PlayMode::Single => PlayMode::TwoPlayer,
+      PlayMode::TwoPlayer => PlayMode::VsAI,
+      PlayMode::VsAI => PlayMode::TimeAttack,
+      PlayMode::TimeAttack => PlayMode::Survival,
+      PlayMode::Survival => PlayMode::Challenge,
+      PlayMode::Challenge => PlayMode::Training,
+      PlayMode::Training => PlayMode::Single
+    }
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      PlayMode::Single => "Single",
+      PlayMode::TwoPlayer => "Two Player",
+      PlayMode::VsAI => "Versus AI",
+      PlayMode::TimeAttack => "Time Attack",
+      PlayMode::Survival => "Survival",
+      PlayMode::Challenge => "Challenge",
+      PlayMode::Training => "Training"
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum GameMode {
+  Menu,
+  // Shown before play starts, the board is frozen until it runs out.
+  Countdown { remaining_ticks: u64 },
+  Playing,
+  Paused,
+  // Wipes the last frame of the board away before showing `to`.
+  Transition { to: Box<GameMode>, ticks_left: u8 },
+  Stats,
+  Settings,
+  Leaderboard,
+  // Typing in the player's name, then either starting a game or going
+  // back to the menu.
+  NameEntry { start_after: bool },
+  // The snake whose crash ended the game loses a cell a tick, tail end
+  // first, before the board is wiped for the dead screen.
+  Dying { second: bool, ticks_left: usize },
+  Dead,
+  // The end of a time attack game the snake lived through.
+  Survived
+}
+
+// The part of a game F2 writes out in debug mode, and --load reads back.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+  player: Player,
+  food: Vec<Food>,
+  score: i32,
+  ticks: u64,
+  mode: GameMode
+}
+
+// Just the name of the mode, without the ticks or targets it carries.
+impl fmt::Display for GameMode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      GameMode::Menu => "Menu",
+      GameMode::Countdown { .. } => "Countdown",
+      GameMode::Playing => "Playing",
+      GameMode::Paused => "Paused",
+      GameMode::Transition { .. } => "Transition",
+      GameMode::Stats => "Stats",
+      GameMode::Settings => "Settings",
+      GameMode::Leaderboard => "Leaderboard",
+      GameMode::NameEntry { .. } => "NameEntry",
+      GameMode::Dying { .. } => "Dying",
+      GameMode::Dead => "Dead",
+      GameMode::Survived => "Survived"
+    };
+    f.write_str(name)
+  }
+}
+
+impl GameMode {
+  // Menu -> NameEntry, Stats, Settings, Leaderboard or Countdown
+  // NameEntry -> Menu or Countdown
+  // Stats, Settings, Leaderboard -> Menu
+  // Countdown -> Countdown with a tick less, or Playing
+  // Playing -> Paused, Countdown for the next level, Dying, or Transition
+  //   to Survived
+  // Dying -> Dying with a cell less, or Transition to Dead
+  // Paused -> Playing
+  // Transition -> whatever it was heading for
+  // Dead, Survived -> Menu, or Countdown to watch the replay
+  fn can_transition_to(&self, next: &GameMode) -> bool {
+    match (self, next) {
+      (GameMode::Menu, GameMode::NameEntry { .. } | GameMode::Stats | GameMode::Settings
+        | GameMode::Leaderboard | GameMode::Countdown { .. }) => true,
+      (GameMode::NameEntry { .. }, GameMode::Menu | GameMode::Countdown { .. }) => true,
+      (GameMode::Stats | GameMode::Settings | GameMode::Leaderboard, GameMode::Menu) => true,
+      (GameMode::Countdown { .. }, GameMode::Countdown { .. } | GameMode::Playing) => true,
+      (GameMode::Playing, GameMode::Paused | GameMode::Countdown { .. } | GameMode::Dying { .. }) => true,
+      (GameMode::Playing, GameMode::Transition { to, .. }) => matches!(**to, GameMode::Survived),
+      (GameMode::Dying { .. }, GameMode::Dying { .. }) => true,
+      (GameMode::Dying { .. }, GameMode::Transition { to, .. }) => matches!(**to, GameMode::Dead),
+      (GameMode::Paused, GameMode::Playing) => true,
+      (GameMode::Transition { to, .. }, next) => std::mem::discriminant(&**to) == std::mem::discriminant(next),
+      (GameMode::Dead | GameMode::Survived, GameMode::Menu | GameMode::Countdown { .. }) => true,
+      _ => false
+    }
+  }
+}
+
+pub struct State {
+  logic: GameLogic,
+  mode: GameMode,
+  // The best score for each difficulty that has been played.
+  high_scores: HashMap<Difficulty, i32>,
+  // Set when the game that just ended set a new best.
+  beat_high_score: bool,
+  // Never reset by restart, saved at the end of every game.
+  stats: Stats,
+  achievements: HashSet<Achievement>,
+  // The latest unlock and the ticks its banner has left.
+  achievement_banner: Option<(Achievement, u64)>,
+  key_bindings: KeyBindings,
+  gamepad: Gamepad,
+  // The highlighted line on the settings screen.
+  settings_row: usize,
+  // Asked for before the first game, and kept in ~/.snek_name.
+  player_name: String,
+  leaderboard: Leaderboard,
+  // The first player's head after each move of the run that set the best
+  // score.
+  ghost: Vec<(u64, Cell)>,
+  // F1 toggles an overlay with the first player's state on the board.
+  debug: bool,
+  // Frame time not yet used up by game ticks.
+  time_accumulator: f64,
+  // Ticks of screen shake left, and how far the board is moved this tick.
+  // The offsets come from their own generator so the game's rng, and with
+  // it replays, is never touched.
+  shake_ticks: u8,
+  shake_offset: (f32, f32),
+  effects_rng: RandomNumberGenerator,
+  // Where food was last eaten and the ticks its flash has left.
+  eat_animation: Option<(Cell, u8)>,
+  // Why the last replay couldn't be watched, shown until the next game.
+  replay_error: Option<String>,
+  saves: SaveFiles,
+}
+
+impl State {
+  pub fn new(difficulty: Difficulty, saves: SaveFiles) -> Self {
+      State {
+        logic: GameLogic::new(difficulty),
+        mode: GameMode::Menu,
+        high_scores: load_high_scores(&saves),
+        beat_high_score: false,
+        stats: Stats::load(&saves),
+        achievements: load_achievements(&saves),
+        achievement_banner: None,
+        key_bindings: KeyBindings::load(&saves),
+        gamepad: Gamepad::new(),
+        settings_row: 0,
+        player_name: load_name(&saves),
+        leaderboard: Leaderboard::load(&saves),
+        ghost: load_ghost(&saves),
+        debug: false,
+        time_accumulator: 0.0,
+        shake_ticks: 0,
+        shake_offset: (0.0, 0.0),
+        effects_rng: RandomNumberGenerator::new(),
+        eat_animation: None,
+        replay_error: None,
+        saves,
+      }
+  }
+
+  fn render_food(&mut self, ctx: &mut BTerm) {
+    for portal in self.logic.portals.iter_mut() {
+      portal.render(ctx, &self.logic.config);
+    }
+    for (index, food) in self.logic.food.iter_mut().enumerate() {
+      if self.logic.play_mode == PlayMode::Challenge {
+        // The one to eat next stands out from the rest.
+        let glyph = if index == self.logic.challenge_next { '☼' } else { '@' };
+        food.pos.render_glyph(ctx, &self.logic.config, RGB::named(CHALLENGE_COLORS[index].1), to_cp437(glyph));
+      } else {
+        food.render(ctx, &self.logic.config, self.logic.ticks);
+      }
+    }
+    if let Some(bonus) = self.logic.bonus.as_mut() {
+      bonus.render(ctx, &self.logic.config);
+    }
+    if let Some(mobile) = self.logic.mobile_food.as_mut() {
+      mobile.render(ctx, &self.logic.config);
+    }
+    if let Some((kind, pos)) = self.logic.power_up_item.as_mut() {
+      kind.render(pos, ctx, &self.logic.config);
+    }
+  }
+
+  fn render_obstacles(&mut self, ctx: &mut BTerm) {
+    let color = RGB::named(self.logic.config.theme.colors().obstacle);
+    for obstacle in self.logic.obstacles.iter_mut() {
+      obstacle.render_glyph(ctx, &self.logic.config, color, to_cp437('#'));
+    }
+    for wall in self.logic.walls.iter_mut() {
+      wall.render_glyph(ctx, &self.logic.config, color, to_cp437('█'));
+    }
+    for wall in self.logic.moving_walls.iter_mut() {
+      wall.render(ctx, &self.logic.config);
+    }
+  }
+
+  fn menu(&mut self, ctx: &mut BTerm) {
+    ctx.cls();
+    let text = self.logic.config.theme.colors().text;
+    ctx.print_color_centered(5, text, BLACK, "Welcome to Snek");
+    let records: Vec<String> = DIFFICULTY_SETTINGS.iter()
+      .map(|(name, difficulty)| format!("{} {}", name, self.high_scores.get(difficulty).unwrap_or(&0)))
+      .collect();
+    ctx.print_color_centered(6, text, BLACK, format!("Best: {}", records.join("  ")));
+    ctx.print_color_centered(8, text, BLACK, "(P) Play Game");
+    ctx.print_color_centered(9, text, BLACK, "(Q) Quit Game");
+    ctx.print_color_centered(11, text, BLACK, format!("(B) Board: {}", self.logic.board_mode.name()));
+    ctx.print_color_centered(12, text, BLACK, format!("(M) Mode: {}", self.logic.play_mode.name()));
+    ctx.print_color_centered(13, text, BLACK, "(S) Statistics");
+    ctx.print_color_centered(14, text, BLACK, "(O) Settings");
+    ctx.print_color_centered(15, text, BLACK, format!("(D) Difficulty: {}", self.logic.config.difficulty.name()));
+    ctx.print_color_centered(16, text, BLACK, format!("(N) Name: {}", self.player_name));
+    ctx.print_color_centered(17, text, BLACK, "(L) Leaderboard");
+
+    if let Some(key) = ctx.key {
+        match key {
+            VirtualKeyCode::P if self.player_name.is_empty() => {
+              self.set_mode(GameMode::NameEntry { start_after: true });
+            }
+            VirtualKeyCode::P => self.start_game(),
+            VirtualKeyCode::N => self.set_mode(GameMode::NameEntry { start_after: false }),
+            VirtualKeyCode::B => self.logic.board_mode = self.logic.board_mode.next(),
+            VirtualKeyCode::M => self.logic.play_mode = self.logic.play_mode.next(),
+            VirtualKeyCode::S => self.set_mode(GameMode::Stats),
+            VirtualKeyCode::O => self.set_mode(GameMode::Settings),
+            VirtualKeyCode::L => self.set_mode(GameMode::Leaderboard),
+            VirtualKeyCode::D => {
+              let difficulty = cycle_setting(&DIFFICULTY_SETTINGS, self.logic.config.difficulty, true);
+              self.set_difficulty(difficulty);
+              self.logic.config.save(&self.saves);
+            }
+            VirtualKeyCode::Q => ctx.quitting = true,
+            _ => {}
+        }
+    }
+  } 
+
+  fn start_game(&mut self) {
+    self.set_mode(GameMode::Countdown { remaining_ticks: COUNTDOWN_TICKS });
+    self.logic.replaying = false;
+    self.restart();
+  }
+
+  fn name_entry(&mut self, ctx: &mut BTerm, start_after: bool) {
+    ctx.cls();
+    let text = self.logic.config.theme.colors().text;
+    ctx.print_color_centered(5, text, BLACK, "Enter your name");
+    ctx.print_color_centered(7, YELLOW, BLACK, format!("{}_", self.player_name));
+    ctx.print_color_centered(9, text, BLACK, "(Enter) Done");
+
+    let Some(key) = ctx.key else { return };
+    match key {
+      VirtualKeyCode::Back => {
+        self.player_name.pop();
+      }
+      VirtualKeyCode::Return if !self.player_name.is_empty() => {
+        save_name(&self.saves, &self.player_name);
+        if start_after {
+          self.start_game();
+        } else {
+          self.set_mode(GameMode::Menu);
+        }
+      }
+      _ => {
+        if let Some(c) = name_char(key).filter(|_| self.player_name.len() < MAX_NAME_LEN) {
+          self.player_name.push(c);
+        }
+      }
+    }
+  }
+
+  fn restart(&mut self) {
+    let seed = self.logic.config.game_seed();
+    self.restart_with_seed(seed);
+  }
+
+  fn restart_with_seed(&mut self, seed: u64) {
+    self.logic.restart(seed);
+    self.beat_high_score = false;
+    self.achievement_banner = None;
+    self.time_accumulator = 0.0;
+    self.shake_ticks = 0;
+    self.shake_offset = (0.0, 0.0);
+    self.eat_animation = None;
+    self.replay_error = None;
+  }
+
+  // Plays the last saved game again with the same seed and inputs, which
+  // makes it turn out exactly the same.
+  fn watch_replay(&mut self) {
+    let Some(replay) = Replay::load(&self.saves) else { return };
+    let config = GameConfig {
+      screen_width: self.logic.config.screen_width,
+      screen_height: self.logic.config.screen_height,
+      ticks_per_move: replay.ticks_per_move,
+      pixel_scale: replay.pixel_scale,
+      lives: replay.lives,
+      seed: self.logic.config.seed,
+      theme: self.logic.config.theme,
+      background: self.logic.config.background,
+      ..replay.difficulty.config()
+    };
+    let board = config.board();
+    if (board.width, board.height) != (replay.width, replay.height) {
+      self.replay_error = Some(format!("The replay was played on a {}x{} board, this window fits {}x{}",
+        replay.width, replay.height, board.width, board.height));
+      return;
+    }
+    self.logic.board_mode = replay.board_mode;
+    self.logic.play_mode = replay.play_mode;
+    self.logic.config = config;
+    self.logic.replaying = true;
+    self.set_mode(GameMode::Countdown { remaining_ticks: COUNTDOWN_TICKS });
+    self.restart_with_seed(replay.seed);
+    self.logic.replay = replay.inputs;
+    self.logic.replay_hints = replay.hints;
+  }
+
+  // While replaying, the first player's turns come from the replay in
+  // update instead.
+  // click is the board cell under the mouse when the left button went down.
+  fn handle_input(&mut self, key: Option<VirtualKeyCode>, click: Option<Cell>) {
+    if self.is_pause_key(key) {
+      self.set_mode(GameMode::Paused);
+      return;
+    }
+    if !self.logic.replaying {
+      // The gamepad only steers the first player.
+      let pad = self.gamepad.poll();
+      let turn = key.and_then(|key| self.logic.player.controls.dir_for(key, &self.key_bindings)).or(pad);
+      self.logic.steer(turn, click);
+    }
+    if let Some(p) = self.logic.player2.as_mut() {
+      p.update_direction(key, &self.key_bindings);
+    }
+    if key == Some(VirtualKeyCode::H) && !self.logic.replaying {
+      self.logic.use_hint();
+    }
+  }
+
+  // The board cell drawn at a console position, if there is one.
+  fn cell_at(&self, (x, y): (i32, i32)) -> Option<Cell> {
+    self.logic.config.board().cell_at((x, y))
+  }
+
+  // One tick of the game, and of everything on screen that reacts to it.
+  fn update(&mut self) {
+    if let Some((_, ticks_left)) = self.eat_animation.as_mut() {
+      *ticks_left -= 1;
+    }
+    self.eat_animation.take_if(|(_, ticks_left)| *ticks_left == 0);
+    for event in self.logic.step() {
+      match event {
+        Event::Ate(cell) => {
+          self.eat_animation = Some((cell, EAT_FLASH_TICKS));
+          if self.logic.is_recorded() {
+            self.stats.total_food_eaten += 1;
+          }
+        }
+        Event::LostLife => self.shake_ticks = SHAKE_TICKS,
+        Event::Died { second } => {
+          let ticks_left = self.logic.snake(second).len();
+          self.set_mode(GameMode::Dying { second, ticks_left });
+          self.finish_game();
+        }
+        Event::Survived => {
+          self.set_mode(GameMode::Transition { to: Box::new(GameMode::Survived), ticks_left: TRANSITION_TICKS });
+          self.finish_game();
+        }
+        Event::LevelUp => self.set_mode(GameMode::Countdown { remaining_ticks: COUNTDOWN_TICKS }),
+      }
+    }
+    self.update_achievements();
+  }
+
+  fn update_achievements(&mut self) {
+    if !self.logic.is_recorded() {
+      return;
+    }
+    if let Some((_, ticks_remaining)) = self.achievement_banner.as_mut() {
+      *ticks_remaining = ticks_remaining.saturating_sub(1);
+    }
+    self.achievement_banner.take_if(|(_, ticks_remaining)| *ticks_remaining == 0);
+    let earned = [
+      (Achievement::FirstFood, self.logic.food_eaten() > 0),
+      (Achievement::Length10, self.logic.player.len() >= 10),
+      (Achievement::Score100, self.logic.score >= 100),
+      (Achievement::NoTurns, self.logic.player.straight_moves >= 30),
+      (Achievement::SurvivedLevel5, self.logic.level > 5),
+    ];
+    for (achievement, done) in earned {
+      if done && self.achievements.insert(achievement) {
+        self.achievement_banner = Some((achievement, ACHIEVEMENT_BANNER_TICKS));
+        save_achievements(&self.saves, &self.achievements);
+      }
+    }
+  }
+
+  // Drawn on its own layer, and only where nothing else is, so it shows
+  // up underneath the live game.
+  fn render_ghost(&mut self, ctx: &mut BTerm) {
+    let shown = self.ghost.partition_point(|(tick, _)| *tick <= self.logic.ticks);
+    if shown == 0 {
+      return;
+    }
+    let occupied = self.logic.occupied_cells();
+    ctx.set_active_console(GHOST_CONSOLE);
+    for (_, cell) in self.ghost[shown.saturating_sub(GHOST_TRAIL)..shown].iter_mut() {
+      if !occupied.contains(cell) {
+        cell.render_glyph(ctx, &self.logic.config, RGB::named(GREY30), to_cp437('·'));
+      }
+    }
+    ctx.set_active_console(0);
+  }
+
+  // Every change of screen goes through here. A change the graph in
+  // can_transition_to does not allow is a bug, so debug builds stop on it.
+  fn set_mode(&mut self, next: GameMode) {
+    if !self.mode.can_transition_to(&next) {
+      let message = format!("invalid mode change from {:?} to {:?}", self.mode, next);
+      if cfg!(debug_assertions) {
+        panic!("{}", message);
+      }
+      eprintln!("{}", message);
+    }
+    self.mode = next;
+  }
+
+  fn to_json(&self) -> String {
+    let snapshot = Snapshot {
+      player: self.logic.player.clone(),
+      food: self.logic.food.clone(),
+      score: self.logic.score,
+      ticks: self.logic.ticks,
+      mode: self.mode.clone()
+    };
+    serde_json::to_string_pretty(&snapshot).unwrap_or_default()
+  }
+
+  // Starts from a fresh game with config and puts the snapshot on top of
+  // it. Anything off that board, or a transition with more than
+  // TRANSITION_TICKS left, is rejected.
+  fn from_json(json: &str, config: GameConfig, saves: SaveFiles) -> Result<State, serde_json::Error> {
+    let snapshot: Snapshot = serde_json::from_str(json)?;
+    let on_board = |cell: &Cell| config.board().contains(*cell);
+    let player = &snapshot.player;
+    let mut cells = std::iter::once(&player.head)
+      .chain(player.tail.iter())
+      .chain(snapshot.food.iter().map(|food| &food.pos));
+    if let Some(cell) = cells.find(|cell| !on_board(cell)) {
+      return Err(serde::de::Error::custom(format!("cell ({}, {}) is off the board", cell.x, cell.y)));
+    }
+    let mut mode = &snapshot.mode;
+    while let GameMode::Transition { to, ticks_left } = mode {
+      if *ticks_left > TRANSITION_TICKS {
+        return Err(serde::de::Error::custom(format!("a transition can't have {} ticks left", ticks_left)));
+      }
+      mode = to;
+    }
+    let mut state = State::new(config.difficulty, saves);
+    state.logic.config = config;
+    let color = state.logic.player.color;
+    state.logic.player = snapshot.player;
+    state.logic.player.color = color;
+    state.logic.food = snapshot.food;
+    state.logic.score = snapshot.score;
+    state.logic.ticks = snapshot.ticks;
+    state.mode = snapshot.mode;
+    Ok(state)
+  }
+
+  // Written next to wherever the game was started from.
+  fn dump_state(&self) {
+    let _ = fs::write("state_dump.json", self.to_json());
+  }
+
+  fn render_debug(&mut self, ctx: &mut BTerm) {
+    let player = &self.logic.player;
+    let lines = [
+      format!("head: {}", player.head),
+      format!("tail: {}", player.tail.len()),
+      format!("ticks: {}", self.logic.ticks),
+      format!("dir: {}", player.dir),
+      format!("score: {}", self.logic.score),
+      format!("mode: {}", self.mode),
+    ];
+    for (row, line) in lines.iter().enumerate() {
+      ctx.print_color(1, 1 + row as i32, MAGENTA, BLACK, line);
+    }
+  }
+
+  fn render(&mut self, ctx: &mut BTerm) {
+    let (dx, dy) = self.shake_offset;
+    for console in [0, BORDER_CONSOLE, GHOST_CONSOLE] {
+      ctx.set_active_console(console);
+      ctx.set_offset(dx, dy);
+    }
+    ctx.set_active_console(0);
+    ctx.cls_bg(RGB::named(self.logic.config.theme.colors().background));
+    self.render_background(ctx);
+    self.render_ghost(ctx);
+    self.render_obstacles(ctx);
+    self.render_food(ctx);
+    if self.logic.is_alive(false) {
+      self.logic.player.render(ctx, &self.logic.config, self.logic.ticks);
+    }
+    if self.logic.is_alive(true) {
+      if let Some(p) = self.logic.player2.as_mut() {
+        p.render(ctx, &self.logic.config, self.logic.ticks);
+      }
+    }
+    if let Some(ai) = self.logic.ai.as_mut() {
+      ai.player.render(ctx, &self.logic.config, self.logic.ticks);
+    }
+    if let Some(mut target) = self.logic.player.target {
+      target.render_glyph(ctx, &self.logic.config, RGB::named(YELLOW), to_cp437('+'));
+    }
+    if let Some(dir) = self.logic.hint {
+      let mut ahead = Cell::step(self.logic.player.head, dir);
+      ahead.render_head(&dir, ctx, &self.logic.config, RGB::named(YELLOW));
+    }
+    // On top of the head that just ate.
+    if let Some((mut cell, _)) = self.eat_animation {
+      cell.render_glyph(ctx, &self.logic.config, RGB::named(WHITE), to_cp437('*'));
+    }
+    self.render_border(ctx);
+    ctx.set_active_console(HUD_CONSOLE);
+    self.render_hud(ctx);
+    if let Some((achievement, _)) = self.achievement_banner {
+      let banner = format!("Achievement Unlocked: {}!", achievement.name());
+      ctx.print_color_centered(1, GOLD, BLACK, banner);
+    }
+    if self.logic.trapped && self.logic.is_alive(false) {
+      ctx.print_color_centered(2, RED, BLACK, "Trapped!");
+    }
+    if self.debug {
+      self.render_debug(ctx);
+    }
+    ctx.set_active_console(0);
+  }
+
+  // A shade off the theme's background, so it never competes with what is
+  // drawn on top.
+  fn render_background(&self, ctx: &mut BTerm) {
+    let board = self.logic.config.board();
+    let scale = board.pixel_scale;
+    let background = RGB::named(self.logic.config.theme.colors().background);
+    let shade = background.lerp(RGB::named(WHITE), 0.08);
+    let dot = background.lerp(RGB::named(WHITE), 0.25);
+    for cell in board.cells() {
+      let (x, y) = board.to_pixel(cell);
+      match self.logic.config.background {
+        BackgroundStyle::Solid => return,
+        BackgroundStyle::Checkered if (cell.x + cell.y) % 2 == 1 => {
+          for dy in 0..scale {
+            for dx in 0..scale {
+              ctx.set_bg(x+dx, y+dy, shade);
+            }
+          }
+        }
+        BackgroundStyle::Checkered => {}
+        BackgroundStyle::DotGrid => ctx.set(x + scale/2, y + scale/2, dot, background, to_cp437('·')),
+      }
+    }
+  }
+
+  fn render_border(&mut self, ctx: &mut BTerm) {
+    // Drawn on its own layer so it never overwrites board cells.
+    let board = self.logic.config.board();
+    ctx.set_active_console(BORDER_CONSOLE);
+    ctx.draw_hollow_box(
+      0, 0,
+      board.width*board.pixel_scale + 1,
+      board.height*board.pixel_scale + 1,
+      RGB::named(GREY50), RGB::named(BLACK));
+    ctx.set_active_console(0);
+  }
+
+  // Adds the frame to time_accumulator and takes out the whole ticks that
+  // have passed, so the game and the animations run at the same speed at
+  // any frame rate.
+  fn take_ticks(&mut self, ctx: &BTerm) -> u32 {
+    let elapsed = self.time_accumulator + ctx.frame_time_ms as f64;
+    let elapsed = elapsed.min(MAX_TICKS_PER_FRAME as f64 * TICK_MS);
+    let ticks = (elapsed / TICK_MS) as u32;
+    self.time_accumulator = elapsed - ticks as f64 * TICK_MS;
+    self.update_shake(ticks);
+    ticks
+  }
+
+  // Counted in game ticks like everything else, so the shake lasts as long
+  // at any frame rate and stands still while paused. The board moves to a
+  // new offset every tick until it runs out.
+  fn update_shake(&mut self, ticks: u32) {
+    if ticks == 0 {
+      return;
+    }
+    self.shake_ticks = self.shake_ticks.saturating_sub(ticks.min(SHAKE_TICKS as u32) as u8);
+    self.shake_offset = if self.shake_ticks > 0 {
+      (self.effects_rng.range(-2, 3) as f32, self.effects_rng.range(-2, 3) as f32)
+    } else {
+      (0.0, 0.0)
+    };
+  }
+
+  fn countdown(&mut self, ctx: &mut BTerm, remaining_ticks: u64) {
+    self.render(ctx);
+    let seconds = remaining_ticks.div_ceil(TICKS_PER_SECOND);
+    ctx.print_color_centered(self.logic.config.screen_height/2, YELLOW, BLACK, seconds.to_string());
+    let ticks = self.take_ticks(ctx) as u64;
+    self.set_mode(match remaining_ticks.saturating_sub(ticks) {
+      0 => GameMode::Playing,
+      remaining_ticks => GameMode::Countdown { remaining_ticks }
+    });
+  }
+
+  fn transition(&mut self, ctx: &mut BTerm) {
+    let ticks = self.take_ticks(ctx);
+    let GameMode::Transition { ticks_left, .. } = &mut self.mode else { return };
+    *ticks_left = ticks_left.saturating_sub(ticks as u8);
+    let ticks_left = *ticks_left;
+    if ticks_left == 0 {
+      if let GameMode::Transition { to, .. } = &self.mode {
+        let to = (**to).clone();
+        self.set_mode(to);
+      }
+      return;
+    }
+    // Blank the frozen board from the top down, a few more rows each tick.
+    self.render(ctx);
+    let done = (TRANSITION_TICKS - ticks_left) as i32;
+    let rows = self.logic.config.screen_height * done / TRANSITION_TICKS as i32;
+    for console in [0, BORDER_CONSOLE, GHOST_CONSOLE, HUD_CONSOLE] {
+      ctx.set_active_console(console);
+      for y in 0..rows {
+        for x in 0..self.logic.config.screen_width {
+          ctx.set(x, y, BLACK, BLACK, to_cp437(' '));
+        }
+      }
+    }
+    ctx.set_active_console(0);
+  }
+
+  fn paused(&mut self, ctx: &mut BTerm) {
+    // Draw the frozen board underneath the message, nothing moves.
+    self.render(ctx);
+    ctx.print_color_centered(self.logic.config.screen_height/2, YELLOW, BLACK, "Paused - press Escape to resume");
+
+    if self.is_pause_key(ctx.key) {
+      self.set_mode(GameMode::Playing);
+    }
+  }
+
+  // Escape always pauses, whatever the pause key is bound to.
+  fn is_pause_key(&self, key: Option<VirtualKeyCode>) -> bool {
+    key.is_some_and(|key| key == VirtualKeyCode::Escape || key == self.key_bindings.pause)
+  }
+
+  fn render_hud(&mut self, ctx: &mut BTerm) {
+    // The bottom row is never reached by the snake or the food.
+    let row = self.logic.config.screen_height-1;
+    let mut hud = match self.logic.play_mode {
+      PlayMode::TwoPlayer => format!("P1 {} x{}", self.logic.score, self.logic.lives),
+      _ => format!("Score: {}  Length: {}  Lives: {}", self.logic.score, self.logic.player.len(), self.logic.lives)
+    };
+    if self.logic.combo > 1 {
+      hud.push_str(&format!("  Combo: x{}", self.logic.combo));
+    }
+    for (kind, owner, ticks_remaining) in self.logic.power_ups.iter() {
+      let snake = match owner {
+        Some(true) => "P2 ",
+        Some(false) if self.logic.player2.is_some() => "P1 ",
+        _ => ""
+      };
+      hud.push_str(&format!("  {}{}: {}s", snake, kind.name(), ticks_remaining.div_ceil(TICKS_PER_SECOND)));
+    }
+    match self.logic.play_mode {
+      PlayMode::TimeAttack => {
+        let seconds_left = self.logic.config.time_limit_ticks.saturating_sub(self.logic.ticks).div_ceil(TICKS_PER_SECOND);
+        hud.push_str(&format!("  Time: {}s", seconds_left));
+      }
+      PlayMode::Survival => {
+        let growth_in = SURVIVAL_GROWTH_TICKS - self.logic.ticks % SURVIVAL_GROWTH_TICKS;
+        hud.push_str(&format!("  Growth in: {} ticks", growth_in));
+      }
+      PlayMode::Challenge => hud.push_str(&format!("  Next: {}", CHALLENGE_COLORS[self.logic.challenge_next].0)),
+      PlayMode::Training if self.logic.takeover_ticks > 0 => {
+        hud.push_str(&format!("  You: {}s", self.logic.takeover_ticks.div_ceil(TICKS_PER_SECOND)));
+      }
+      PlayMode::Training => hud.push_str("  Autopilot"),
+      _ => hud.push_str(&format!("  Time: {}", self.logic.elapsed_time()))
+    }
+    let text = self.logic.config.theme.colors().text;
+    ctx.print_color(1, row, text, BLACK, hud);
+    if self.logic.player2.is_some() {
+      let right = format!("P2 {} x{}", self.logic.player2_score, self.logic.player2_lives);
+      ctx.print_color(self.logic.config.screen_width - 1 - right.len() as i32, row, text, BLACK, right);
+    }
+  }
+
+  // Dead snakes are left out of render, so a copy cut down to ticks_left
+  // cells is drawn over the board instead.
+  fn dying(&mut self, ctx: &mut BTerm, second: bool, ticks_left: usize) {
+    self.render(ctx);
+    let snake = if second { self.logic.player2.as_ref() } else { Some(&self.logic.player) };
+    if let Some(mut shown) = snake.filter(|_| ticks_left > 0).cloned() {
+      shown.tail.truncate(ticks_left - 1);
+      shown.render(ctx, &self.logic.config, self.logic.ticks);
+    }
+    let ticks = self.take_ticks(ctx) as usize;
+    self.set_mode(match ticks_left.checked_sub(ticks) {
+      None => GameMode::Transition { to: Box::new(GameMode::Dead), ticks_left: TRANSITION_TICKS },
+      Some(ticks_left) => GameMode::Dying { second, ticks_left }
+    });
+  }
+
+  fn finish_game(&mut self) {
+    if !self.logic.is_recorded() {
+      return;
+    }
+    self.record_high_score();
+    if self.beat_high_score {
+      self.ghost = std::mem::take(&mut self.logic.head_path);
+      save_ghost(&self.saves, &self.ghost);
+    }
+    self.record_stats();
+    let entry = LeaderboardEntry {
+      name: self.player_name.clone(),
+      score: self.logic.score,
+      date: today()
+    };
+    if self.leaderboard.try_insert(entry) {
+      self.leaderboard.save(&self.saves);
+    }
+    // Only the first player's turns are recorded, so two player games
+    // cannot be replayed.
+    if self.logic.play_mode != PlayMode::TwoPlayer {
+      let replay = Replay {
+        seed: self.logic.seed,
+        board_mode: self.logic.board_mode,
+        play_mode: self.logic.play_mode,
+        difficulty: self.logic.config.difficulty,
+        ticks_per_move: self.logic.config.ticks_per_move,
+        pixel_scale: self.logic.config.pixel_scale,
+        width: self.logic.config.board().width,
+        height: self.logic.config.board().height,
+        lives: self.logic.config.lives,
+        inputs: self.logic.replay.clone(),
+        hints: self.logic.replay_hints.clone()
+      };
+      replay.save(&self.saves);
+    }
+  }
+
+  fn record_stats(&mut self) {
+    self.stats.games_played += 1;
+    self.stats.total_distance += self.logic.player.total_distance;
+    self.stats.total_distance += self.logic.player2.as_ref().map_or(0, |p| p.total_distance);
+    self.stats.best_score = self.stats.best_score.max(self.logic.score).max(self.logic.player2_score);
+    self.stats.save(&self.saves);
+  }
+
+  // Changes only take effect from the next game, and are saved on the
+  // way back to the menu.
+  fn settings(&mut self, ctx: &mut BTerm) {
+    let text = self.logic.config.theme.colors().text;
+    let config = &mut self.logic.config;
+    let rows = [
+      format!("Speed: {}", setting_name(&SPEED_SETTINGS, config.ticks_per_move)),
+      format!("Board: {}", setting_name(&BOARD_SETTINGS, config.pixel_scale)),
+      format!("Difficulty: {}", config.difficulty.name()),
+      format!("Theme: {}", config.theme.name()),
+      format!("Background: {}", setting_name(&BACKGROUND_SETTINGS, config.background)),
+    ];
+    ctx.cls();
+    ctx.print_color_centered(5, text, BLACK, "Settings");
+    for (index, row) in rows.iter().enumerate() {
+      let y = 7 + index as i32;
+      if index == self.settings_row {
+        ctx.print_color_centered(y, YELLOW, BLACK, format!("> {} <", row));
+      } else {
+        ctx.print_color_centered(y, text, BLACK, row);
+      }
+    }
+    ctx.print_color_centered(13, text, BLACK, "Up/Down to choose, Left/Right to change");
+    ctx.print_color_centered(14, text, BLACK, "(Escape) Save and return");
+
+    let Some(key) = ctx.key else { return };
+    let forwards = key == VirtualKeyCode::Right;
+    match key {
+      VirtualKeyCode::Up => self.settings_row = (self.settings_row + rows.len() - 1) % rows.len(),
+      VirtualKeyCode::Down => self.settings_row = (self.settings_row + 1) % rows.len(),
+      VirtualKeyCode::Left | VirtualKeyCode::Right => match self.settings_row {
+        0 => config.ticks_per_move = cycle_setting(&SPEED_SETTINGS, config.ticks_per_move, forwards),
+        1 => config.pixel_scale = cycle_setting(&BOARD_SETTINGS, config.pixel_scale, forwards),
+        3 => config.theme = cycle_setting(&THEME_SETTINGS, config.theme, forwards),
+        4 => config.background = cycle_setting(&BACKGROUND_SETTINGS, config.background, forwards),
+        _ => {
+          let difficulty = cycle_setting(&DIFFICULTY_SETTINGS, config.difficulty, forwards);
+          self.set_difficulty(difficulty);
+        }
+      },
+      VirtualKeyCode::Escape => {
+        config.save(&self.saves);
+        self.set_mode(GameMode::Menu);
+      }
+      _ => {}
+    }
+  }
+
+  // Starts the settings over from the preset, keeping the board size.
+  fn set_difficulty(&mut self, difficulty: Difficulty) {
+    self.logic.config = GameConfig {
+      screen_width: self.logic.config.screen_width,
+      screen_height: self.logic.config.screen_height,
+      pixel_scale: self.logic.config.pixel_scale,
+      seed: self.logic.config.seed,
+      theme: self.logic.config.theme,
+      background: self.logic.config.background,
+      ..difficulty.config()
+    };
+  }
+
+  fn leaderboard_screen(&mut self, ctx: &mut BTerm) {
+    ctx.cls();
+    let text = self.logic.config.theme.colors().text;
+    ctx.print_color_centered(5, text, BLACK, "Leaderboard");
+    for (rank, entry) in self.leaderboard.0.iter().enumerate() {
+      let line = format!("{:>2}. {:<8} {:>6}  {}", rank + 1, entry.name, entry.score, entry.date);
+      ctx.print_color_centered(7 + rank as i32, text, BLACK, line);
+    }
+    if self.leaderboard.0.is_empty() {
+      ctx.print_color_centered(7, text, BLACK, "No scores yet");
+    }
+    ctx.print_color_centered(8 + LEADERBOARD_SIZE as i32, text, BLACK, "(M) Main Menu");
+
+    if let Some(VirtualKeyCode::M | VirtualKeyCode::Escape) = ctx.key {
+      self.set_mode(GameMode::Menu);
+    }
+  }
+
+  fn stats_screen(&mut self, ctx: &mut BTerm) {
+    ctx.cls();
+    let text = self.logic.config.theme.colors().text;
+    ctx.print_color_centered(5, text, BLACK, "Statistics");
+    ctx.print_color_centered(7, text, BLACK, format!("Games played: {}", self.stats.games_played));
+    ctx.print_color_centered(8, text, BLACK, format!("Food eaten: {}", self.stats.total_food_eaten));
+    ctx.print_color_centered(9, text, BLACK, format!("Distance traveled: {}", self.stats.total_distance));
+    ctx.print_color_centered(10, text, BLACK, format!("Best score: {}", self.stats.best_score));
+    ctx.print_color_centered(12, text, BLACK, "(M) Main Menu");
+
+    if let Some(VirtualKeyCode::M | VirtualKeyCode::Escape) = ctx.key {
+      self.set_mode(GameMode::Menu);
+    }
+  }
+
+  fn high_score(&self) -> i32 {
+    *self.high_scores.get(&self.logic.config.difficulty).unwrap_or(&0)
+  }
+
+  fn record_high_score(&mut self) {
+    let best = self.logic.score.max(self.logic.player2_score);
+    self.beat_high_score = best > self.high_score();
+    if self.beat_high_score {
+      self.high_scores.insert(self.logic.config.difficulty, best);
+      save_high_scores(&self.saves, &self.high_scores);
+    }
+  }
+
+  fn render_new_high_score(&mut self, ctx: &mut BTerm) {
+    if self.beat_high_score {
+      ctx.print_color_centered(3, GOLD, BLACK, "*** NEW HIGH SCORE! ***");
+    }
+  }
+
+  fn render_replay_error(&self, ctx: &mut BTerm) {
+    if let Some(error) = &self.replay_error {
+      ctx.print_color_centered(18, RED, BLACK, error);
+    }
+  }
+
+  fn survived(&mut self, ctx: &mut BTerm) {
+    ctx.cls();
+    let text = self.logic.config.theme.colors().text;
+    self.render_new_high_score(ctx);
+    ctx.print_color_centered(5, text, BLACK, format!("You survived, {}!", self.player_name));
+    ctx.print_color_centered(6, text, BLACK, format!("Score: {}  Best: {}", self.logic.score, self.high_score()));
+    ctx.print_color_centered(7, text, BLACK, format!("Time bonus: {}", self.logic.time_bonus));
+    ctx.print_color_centered(8, text, BLACK, "(M) Main Menu");
+    ctx.print_color_centered(9, text, BLACK, "(Q) Quit Game");
+    ctx.print_color_centered(13, text, BLACK, "(R) Watch Replay");
+    self.render_replay_error(ctx);
+
+    if let Some(key) = ctx.key {
+      match key {
+          VirtualKeyCode::M => self.set_mode(GameMode::Menu),
+          VirtualKeyCode::R => self.watch_replay(),
+          VirtualKeyCode::Q => ctx.quitting = true,
+          _ => {}
+      }
+    }
+  }
+
+  fn dead(&mut self, ctx: &mut BTerm) {
+    ctx.cls();
+    let text = self.logic.config.theme.colors().text;
+    self.render_new_high_score(ctx);
+    ctx.print_color_centered(5, text, BLACK, format!("You are dead, {}!", self.player_name));
+    ctx.print_color_centered(6, text, BLACK, format!("Score: {}  Length: {}  Best: {}", self.logic.score, self.logic.player.len(), self.high_score()));
+    if self.logic.player2.is_some() {
+      ctx.print_color_centered(7, text, BLACK, format!("Player 2 score: {}", self.logic.player2_score));
+    }
+    ctx.print_color_centered(10, text, BLACK, format!("Time: {}  Level: {}", self.logic.elapsed_time(), self.logic.level));
+    ctx.print_color_centered(11, text, BLACK, format!("Seed: {:08X}", self.logic.seed));
+    ctx.print_color_centered(12, text, BLACK, format!("Distance traveled: {}", self.logic.player.total_distance()));
+    ctx.print_color_centered(14, text, BLACK, format!("Difficulty: {}", self.logic.config.difficulty.name()));
+    ctx.print_color_centered(15, text, BLACK, format!("Hints used: {}", self.logic.hints_used));
+    ctx.print_color_centered(16, text, BLACK, format!("Food eaten: {}", self.logic.food_eaten()));
+    ctx.print_color_centered(8, text, BLACK, "(M) Main Menu");
+    ctx.print_color_centered(9, text, BLACK, "(Q) Quit Game");
+    ctx.print_color_centered(13, text, BLACK, "(R) Watch Replay");
+    self.render_replay_error(ctx);
+
+    if let Some(key) = ctx.key {
+      match key {
+          VirtualKeyCode::M => self.set_mode(GameMode::Menu),
+          VirtualKeyCode::R => self.watch_replay(),
+          VirtualKeyCode::Q => ctx.quitting = true,
+          _ => {}
+      }
+    }
+  }
+}
+
+impl GameState for State {
+  fn tick(&mut self, ctx: &mut BTerm) {
+    // Only the board screens draw a border, a ghost or a HUD, or shake, so
+    // start every frame without them.
+    for console in [0, BORDER_CONSOLE, GHOST_CONSOLE, HUD_CONSOLE] {
+      ctx.set_active_console(console);
+      ctx.set_offset(0.0, 0.0);
+      if console != 0 {
+        ctx.cls();
+      }
+    }
+    ctx.set_active_console(0);
+    if ctx.key == Some(VirtualKeyCode::F1) {
+      self.debug = !self.debug;
+    }
+    if self.debug && ctx.key == Some(VirtualKeyCode::F2) {
+      self.dump_state();
+    }
+    match self.mode {
+      GameMode::Menu => self.menu(ctx),
+      GameMode::Playing => {
+        let click = ctx.left_click.then(|| self.cell_at(ctx.mouse_pos())).flatten();
+        self.handle_input(ctx.key, click);
+        for _ in 0..self.take_ticks(ctx) {
+          if matches!(self.mode, GameMode::Playing) {
+            self.update();
+          }
+        }
+        self.render(ctx);
+      }
+      GameMode::Countdown { remaining_ticks } => self.countdown(ctx, remaining_ticks),
+      GameMode::Paused => self.paused(ctx),
+      GameMode::Transition { .. } => self.transition(ctx),
+      GameMode::Stats => self.stats_screen(ctx),
+      GameMode::Settings => self.settings(ctx),
+      GameMode::Leaderboard => self.leaderboard_screen(ctx),
+      GameMode::NameEntry { start_after } => self.name_entry(ctx, start_after),
+      GameMode::Dying { second, ticks_left } => self.dying(ctx, second, ticks_left),
+      GameMode::Dead => self.dead(ctx),
+      GameMode::Survived => self.survived(ctx),
+    }
+  }
+}
+
+// Everything main does, so the game can live in the library.
+pub fn run() -> BError {
+  let saves = SaveFiles::home();
+  let mut config = GameConfig::load(&saves);
+  let load_path = match config.apply_args(env::args().skip(1)) {
+    Ok(load_path) => load_path,
+    Err(message) => {
+      eprintln!("snek: {}\n{}", message, USAGE);
+      std::process::exit(2);
+    }
+  };
+  let context = BTermBuilder::simple(config.screen_width, config.screen_height)
+    .unwrap()
+    .with_title("Snek")
+    .with_simple_console_no_bg(config.screen_width, config.screen_height, "terminal8x8.png")
+    .with_simple_console_no_bg(config.screen_width, config.screen_height, "terminal8x8.png")
+    .with_simple_console_no_bg(config.screen_width, config.screen_height, "terminal8x8.png")
+    .build()?;
+  let mut state = match load_path {
+    Some(path) => State::from_json(&fs::read_to_string(path)?, config, saves)?,
+    None => State::new(config.difficulty, saves)
+  };
+  // Keep anything the settings screen changed on top of the preset.
+  state.logic.config = config;
+  main_loop(context, state)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cell_new_sets_both_coordinates() {
+    let cell = Cell::new(3, -2);
+    assert_eq!((cell.x, cell.y), (3, -2));
+  }
+
+  #[test]
+  fn cell_steps_one_cell_each_way() {
+    let cell = Cell::new(5, 5);
+    assert_eq!(Cell::up(cell), Cell::new(5, 4));
+    assert_eq!(Cell::down(cell), Cell::new(5, 6));
+    assert_eq!(Cell::left(cell), Cell::new(4, 5));
+    assert_eq!(Cell::right(cell), Cell::new(6, 5));
+  }
+
+  #[test]
+  fn cells_are_equal_by_position() {
+    assert_eq!(Cell::new(1, 2), Cell::new(1, 2));
+    assert_ne!(Cell::new(1, 2), Cell::new(2, 1));
+  }
+
+  #[test]
+  fn cells_are_copied_not_moved() {
+    let cell = Cell::new(1, 1);
+    let mut copy = cell;
+    copy.x = 9;
+    #[allow(clippy::clone_on_copy)]
+    let clone = cell.clone();
+    assert_eq!(cell, Cell::new(1, 1));
+    assert_eq!(clone, cell);
+    assert_eq!(copy, Cell::new(9, 1));
+  }
+
+  fn head_at(x: i32, y: i32) -> Player {
+    Player::new_with_length(x, y, 1, Dir::Static)
+  }
+
+  #[test]
+  fn corners_are_in_bounds() {
+    let config = GameConfig::default();
+    let board = config.board();
+    for (x, y) in [(0, 0), (board.width - 1, board.height - 1)] {
+      assert!(board.contains(Cell::new(x, y)));
+      assert!(!head_at(x, y).is_out_of_bounds(&config));
+    }
+  }
+
+  #[test]
+  fn one_past_each_edge_is_out_of_bounds() {
+    let config = GameConfig::default();
+    let board = config.board();
+    let beyond = [(-1, 0), (0, -1), (board.width, 0), (0, board.height)];
+    for (x, y) in beyond {
+      assert!(!board.contains(Cell::new(x, y)), "({}, {})", x, y);
+      assert!(head_at(x, y).is_out_of_bounds(&config), "({}, {})", x, y);
+    }
+  }
+
+  #[test]
+  fn right_and_left_change_only_x() {
+    assert_eq!(Cell::right(Cell::new(5, 5)).x, 6);
+    assert_eq!(Cell::left(Cell::new(5, 5)).x, 4);
+    assert_eq!(Cell::right(Cell::new(5, 5)).y, 5);
+    assert_eq!(Cell::left(Cell::new(5, 5)).y, 5);
+  }
+
+  #[test]
+  fn single_grown_segment_follows_onto_old_head() {
+    let mut player = head_at(5, 5);
+    player.grow();
+    player.dir = Dir::Right;
+    player.update_position();
+    assert_eq!(player.head, Cell::new(6, 5));
+    assert_eq!(player.tail, Tail::from([Cell::new(5, 5)]));
+  }
+
+  #[test]
+  fn grown_segment_stays_on_old_tail_end() {
+    let mut player = Player::new_with_length(5, 5, 3, Dir::Right);
+    let end = *player.tail.back().unwrap();
+    player.grow();
+    player.update_position();
+    assert_eq!(player.tail.len(), 3);
+    assert_eq!(player.tail.back(), Some(&end));
+    assert_eq!(player.tail.front(), Some(&Cell::new(5, 5)));
+  }
+
+  #[test]
+  fn manhattan_distance_is_symmetric() {
+    let (a, b) = (Cell::new(2, 7), Cell::new(-3, 4));
+    assert_eq!(a.manhattan_distance(&b), 8);
+    assert_eq!(b.manhattan_distance(&a), 8);
+  }
+
+  #[test]
+  fn manhattan_distance_to_itself_is_zero() {
+    let cell = Cell::new(4, 9);
+    assert_eq!(cell.manhattan_distance(&cell), 0);
+  }
+
+  #[test]
+  fn occupies_head_and_every_tail_cell() {
+    let player = Player::new_with_length(5, 5, 4, Dir::Right);
+    assert!(player.occupies(player.head));
+    for cell in &player.tail {
+      assert!(player.occupies(*cell));
+    }
+  }
+
+  #[test]
+  fn tail_occupies_leaves_out_the_head() {
+    let player = Player::new_with_length(5, 5, 4, Dir::Right);
+    assert!(!player.tail_occupies(player.head));
+    assert!(player.tail.iter().all(|cell| player.tail_occupies(*cell)));
+  }
+
+  #[test]
+  fn does_not_occupy_an_off_grid_cell() {
+    let player = Player::new_with_length(5, 5, 4, Dir::Right);
+    assert!(!player.occupies(Cell::new(-1, -1)));
+  }
+
+  #[test]
+  fn every_direction_has_its_opposite() {
+    assert_eq!(Dir::Left.opposite(), Dir::Right);
+    assert_eq!(Dir::Right.opposite(), Dir::Left);
+    assert_eq!(Dir::Up.opposite(), Dir::Down);
+    assert_eq!(Dir::Down.opposite(), Dir::Up);
+    assert_eq!(Dir::Static.opposite(), Dir::Static);
+  }
+
+  #[test]
+  fn static_is_never_opposite() {
+    assert!(Dir::Left.is_opposite(Dir::Right));
+    assert!(!Dir::Left.is_opposite(Dir::Up));
+    assert!(!Dir::Static.is_opposite(Dir::Static));
+  }
+
+  #[test]
+  fn every_edge_cell_is_on_the_board() {
+    let board = GameConfig::default().board();
+    for x in 0..board.width {
+      assert!(board.contains(Cell::new(x, 0)));
+      assert!(board.contains(Cell::new(x, board.height - 1)));
+    }
+    for y in 0..board.height {
+      assert!(board.contains(Cell::new(0, y)));
+      assert!(board.contains(Cell::new(board.width - 1, y)));
+    }
+  }
+
+  #[test]
+  fn leaving_any_edge_hits_the_wall() {
+    let config = GameConfig::default();
+    let board = config.board();
+    let (right, bottom) = (board.width - 1, board.height - 1);
+    let edges = [(0, 3, Dir::Left), (right, 3, Dir::Right), (3, 0, Dir::Up), (3, bottom, Dir::Down)];
+    for (x, y, dir) in edges {
+      let mut player = head_at(x, y);
+      assert!(!player.check_walls(BoardMode::Classic, &config));
+      player.dir = dir;
+      player.update_position();
+      assert!(player.check_walls(BoardMode::Classic, &config), "{:?}", dir);
+    }
+  }
+
+  #[test]
+  fn food_new_at_is_normal_food_at_that_cell() {
+    let food = Food::new_at(3, 4);
+    assert_eq!(food.pos, Cell::new(3, 4));
+    assert!(food.kind == FoodKind::Normal);
+  }
+
+  #[test]
+  fn can_move_in_stops_at_each_edge() {
+    let board = GameConfig::default().board();
+    let (right, bottom) = (board.width - 1, board.height - 1);
+    let edges = [(0, 3, Dir::Left), (right, 3, Dir::Right), (3, 0, Dir::Up), (3, bottom, Dir::Down)];
+    for (x, y, outwards) in edges {
+      let player = head_at(x, y);
+      for dir in [Dir::Left, Dir::Right, Dir::Up, Dir::Down] {
+        assert_eq!(player.can_move_in(dir, &board), dir != outwards, "{:?} at ({}, {})", dir, x, y);
+      }
+      assert!(!player.can_move_in(Dir::Static, &board));
+    }
+  }
+
+  #[test]
+  fn can_move_onto_the_tail_end_but_not_the_body() {
+    let board = GameConfig::default().board();
+    let mut player = head_at(5, 5);
+    // Curled round so the tail end is just left of the head.
+    player.tail = Tail::from([Cell::new(5, 6), Cell::new(4, 6), Cell::new(4, 5)]);
+    assert!(player.can_move_in(Dir::Left, &board));
+    assert!(!player.can_move_in(Dir::Down, &board));
+    assert!(player.can_move_in(Dir::Up, &board));
+  }
+
+  #[test]
+  fn cell_displays_as_a_pair() {
+    assert_eq!(Cell::new(3, -1).to_string(), "(3, -1)");
+  }
+
+  #[test]
+  fn dir_displays_as_the_name_from_name_reads() {
+    assert_eq!(Dir::Left.to_string(), "Left");
+    assert_eq!(Dir::Static.to_string(), "Static");
+    for dir in [Dir::Left, Dir::Right, Dir::Up, Dir::Down] {
+      assert_eq!(Dir::from_name(&dir.to_string()), Some(dir));
+    }
+  }
+
+  #[test]
+  fn game_mode_displays_without_its_fields() {
+    assert_eq!(GameMode::Playing.to_string(), "Playing");
+    assert_eq!(GameMode::Dead.to_string(), "Dead");
+    assert_eq!(GameMode::Countdown { remaining_ticks: 3 }.to_string(), "Countdown");
+  }
+
+  // Counts the hidden/shown switches over a run of ticks counting down.
+  fn blink_changes(ticks_left: std::ops::RangeInclusive<u64>) -> usize {
+    let shown: Vec<bool> = ticks_left.rev().map(blink_visible).collect();
+    shown.windows(2).filter(|pair| pair[0] != pair[1]).count()
+  }
+
+  #[test]
+  fn blinking_speeds_up_instead_of_stopping() {
+    let slow = blink_changes(61..=80);
+    let faster = blink_changes(21..=40);
+    let fastest = blink_changes(1..=20);
+    assert!(slow > 0);
+    assert!(faster > slow);
+    assert!(fastest > faster);
+  }
+
+  fn args(list: &[&str]) -> Result<Option<String>, String> {
+    GameConfig::default().apply_args(list.iter().map(|arg| arg.to_string()))
+  }
+
+  #[test]
+  fn huge_board_is_an_error_not_an_overflow() {
+    assert!(args(&["--width", "1000000000"]).is_err());
+    assert!(args(&["--height", "2147483647"]).is_err());
+  }
+
+  #[test]
+  fn board_cannot_shrink_the_screen_below_the_menus() {
+    assert!(args(&["--width", "10"]).is_err());
+    let mut config = GameConfig::default();
+    config.apply_args(["--width", "15", "--height", "20"].iter().map(|arg| arg.to_string())).unwrap();
+    assert_eq!(config.screen_width, SCREEN_WIDTH);
+    assert_eq!((config.board().width, config.board().height), (15, 20));
+  }
+
+  #[test]
+  fn loading_checks_cells_against_the_given_board() {
+    let mut state = State::new(Difficulty::Normal, SaveFiles::none());
+    state.logic.player = Player::new_with_length(30, 30, 1, Dir::Static);
+    let json = state.to_json();
+    assert!(State::from_json(&json, GameConfig::default(), SaveFiles::none()).is_err());
+    let mut config = GameConfig::default();
+    config.set_board_size(40, 40).unwrap();
+    assert!(State::from_json(&json, config, SaveFiles::none()).is_ok());
+  }
+
+  #[test]
+  fn loading_rejects_an_overlong_transition() {
+    let mut state = State::new(Difficulty::Normal, SaveFiles::none());
+    state.mode = GameMode::Transition { to: Box::new(GameMode::Dead), ticks_left: TRANSITION_TICKS + 1 };
+    assert!(State::from_json(&state.to_json(), GameConfig::default(), SaveFiles::none()).is_err());
+    state.mode = GameMode::Transition { to: Box::new(GameMode::Dead), ticks_left: TRANSITION_TICKS };
+    assert!(State::from_json(&state.to_json(), GameConfig::default(), SaveFiles::none()).is_ok());
+  }
+
+  #[test]
+  fn level_layouts_fill_bigger_boards() {
+    let layout = [Cell::new(0, 0), Cell::new(14, 14)];
+    let board = Board { width: 15, height: 15, pixel_scale: 3 };
+    assert_eq!(board.fit_layout(&layout), layout.to_vec());
+    let board = Board { width: 46, height: 45, pixel_scale: 1 };
+    let walls = board.fit_layout(&layout);
+    assert_eq!(walls.len(), 18);
+    assert!(walls.contains(&Cell::new(0, 0)));
+    assert!(walls.contains(&Cell::new(44, 44)));
+    assert!(walls.iter().all(|cell| board.contains(*cell)));
+  }
+
+  #[test]
+  fn bad_settings_go_back_to_their_defaults() {
+    let mut config: GameConfig = toml::from_str("pixel_scale = 0\nlives = 0\nfood_count = 1000\ninitial_length = 0").unwrap();
+    assert_eq!(config.reset_invalid().len(), 4);
+    let default = GameConfig::default();
+    assert_eq!(config.pixel_scale, default.pixel_scale);
+    assert_eq!(config.lives, default.lives);
+    assert_eq!(config.food_count, default.food_count);
+    assert_eq!(config.initial_length, default.initial_length);
+    assert!(config.reset_invalid().is_empty());
+  }
+
+  #[test]
+  fn negative_pixel_scale_is_rejected() {
+    let mut config: GameConfig = toml::from_str("pixel_scale = -2").unwrap();
+    assert_eq!(config.reset_invalid().len(), 1);
+    assert_eq!(config.pixel_scale, GameConfig::default().pixel_scale);
+  }
+
+  #[test]
+  fn high_scores_are_kept_in_the_save_directory() {
+    let dir = env::temp_dir().join(format!("snek-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let saves = SaveFiles::in_dir(&dir);
+    let mut scores = HashMap::new();
+    scores.insert(Difficulty::Hard, 42);
+    save_high_scores(&saves, &scores);
+    assert_eq!(load_high_scores(&saves), scores);
+    assert!(load_high_scores(&SaveFiles::none()).is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn a_replay_for_another_board_stays_on_the_dead_screen_with_a_message() {
+    let dir = env::temp_dir().join(format!("snek-replay-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let saves = SaveFiles::in_dir(&dir);
+    let replay = Replay {
+      seed: 1,
+      board_mode: BoardMode::Classic,
+      play_mode: PlayMode::Single,
+      difficulty: Difficulty::Normal,
+      ticks_per_move: 5,
+      pixel_scale: 1,
+      width: 3,
+      height: 3,
+      lives: 1,
+      inputs: Vec::new(),
+      hints: Vec::new()
+    };
+    replay.save(&saves);
+    let mut state = State::new(Difficulty::Normal, saves);
+    state.mode = GameMode::Dead;
+    state.watch_replay();
+    assert!(matches!(state.mode, GameMode::Dead));
+    assert!(state.replay_error.as_ref().unwrap().contains("3x3"));
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn mobile_food_never_matches_the_snake_head() {
+    for (_, theme) in &THEME_SETTINGS {
+      let colors = theme.colors();
+      assert_ne!(colors.mobile_food, colors.snake_head, "{}", theme.name());
+    }
+  }
+
+  #[test]
+  fn screen_shake_counts_down_in_game_ticks() {
+    let mut state = State::new(Difficulty::Normal, SaveFiles::none());
+    state.shake_ticks = SHAKE_TICKS;
+    state.update_shake(0);
+    assert_eq!(state.shake_ticks, SHAKE_TICKS);
+    state.update_shake(3);
+    assert_eq!(state.shake_ticks, SHAKE_TICKS - 3);
+    let (dx, dy) = state.shake_offset;
+    assert!((-2.0..=2.0).contains(&dx) && (-2.0..=2.0).contains(&dy));
+    state.update_shake(u32::MAX);
+    assert_eq!(state.shake_ticks, 0);
+    assert_eq!(state.shake_offset, (0.0, 0.0));
+  }
+
+  #[test]
+  fn tail_fades_from_the_head_colour_to_the_tip() {
+    let mut player = Player::new_with_length(20, 5, 11, Dir::Static);
+    player.color = RGB::named(YELLOW);
+    let colors = player.tail_colors(RGB::named(SADDLEBROWN));
+    assert_eq!(colors.len(), 10);
+    assert_eq!(colors[0], YELLOW);
+    assert_eq!(colors[9], SADDLEBROWN);
+    let steps = |channel: fn(&(u8, u8, u8)) -> u8| colors.windows(2)
+      .map(|pair| channel(&pair[1]) as i32 - channel(&pair[0]) as i32)
+      .collect::<Vec<i32>>();
+    for steps in [steps(|c| c.0), steps(|c| c.1), steps(|c| c.2)] {
+      // Each channel only ever moves one way, by about the same amount.
+      assert!(steps.iter().all(|step| *step <= 0) || steps.iter().all(|step| *step >= 0));
+      let (min, max) = (steps.iter().min().unwrap(), steps.iter().max().unwrap());
+      assert!(max - min <= 1);
+    }
+  }
+}
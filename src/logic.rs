@@ -0,0 +1,1077 @@
+use super::*;
+
+// What a tick of the game did that the screens around it need to know
+// about, in the order it happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+  // A snake's head ate food on this cell.
+  Ate(Cell),
+  // Either snake crashed, whether or not it had lives left.
+  LostLife,
+  // The last life ran out, second for the second snake's.
+  Died { second: bool },
+  // A time attack game ran to the end.
+  Survived,
+  // Enough food was eaten to move on, and the board is set up for the next
+  // level.
+  LevelUp
+}
+
+// The rules of a game and everything on its board, with no drawing, no
+// keyboard and no files. State drives it a tick at a time.
+pub struct GameLogic {
+  pub(crate) config: GameConfig,
+  pub(crate) board_mode: BoardMode,
+  pub(crate) play_mode: PlayMode,
+  pub(crate) player: Player,
+  // Only present in two player games. Its snake leaves the board once
+  // player2_lives runs out.
+  pub(crate) player2: Option<Player>,
+  pub(crate) player2_score: i32,
+  pub(crate) player2_lives: u8,
+  // Only present in games against the computer, it never runs out of lives.
+  pub(crate) ai: Option<AiPlayer>,
+  pub(crate) ticks: u64,
+  pub(crate) food: Vec<Food>,
+  pub(crate) bonus: Option<BonusFood>,
+  pub(crate) mobile_food: Option<MobileFood>,
+  pub(crate) portals: Vec<Portal>,
+  pub(crate) obstacles: Vec<Cell>,
+  // The fixed walls of the current level.
+  pub(crate) walls: Vec<Cell>,
+  pub(crate) moving_walls: Vec<MovingWall>,
+  pub(crate) level: u32,
+  pub(crate) level_food: u32,
+  // By either player over the whole game.
+  pub(crate) food_eaten: u32,
+  // A power-up waiting on the board, and the ones currently in effect
+  // with the ticks each has left. Invincibility only protects the snake
+  // that picked it up, true for the second one, the rest don't belong to
+  // a snake.
+  pub(crate) power_up_item: Option<(PowerUpKind, Cell)>,
+  pub(crate) power_ups: Vec<(PowerUpKind, Option<bool>, u64)>,
+  // Used for everything on the board except the regular food.
+  pub(crate) rng: RandomNumberGenerator,
+  pub(crate) score: i32,
+  pub(crate) seed: u64,
+  pub(crate) lives: u8,
+  pub(crate) time_bonus: i32,
+  pub(crate) last_food_tick: u64,
+  pub(crate) combo: u32,
+  // Index into CHALLENGE_COLORS, and into food, of the one to eat next.
+  pub(crate) challenge_next: usize,
+  // Turns queued by the first player this game. While replaying they are
+  // fed back in from replay_pos instead of being read from the keyboard,
+  // and nothing about the game is recorded.
+  pub(crate) replay: Vec<(u64, Dir)>,
+  pub(crate) replay_pos: usize,
+  pub(crate) replay_hints: Vec<u64>,
+  pub(crate) replaying: bool,
+  // Where the first player's head was after each move this game.
+  pub(crate) head_path: Vec<(u64, Cell)>,
+  // Set after a move that left the first player with too few free cells
+  // to fit in.
+  pub(crate) trapped: bool,
+  // Pressing H points the way to the nearest food for hint_ticks.
+  pub(crate) hint: Option<Dir>,
+  pub(crate) hint_ticks: u64,
+  pub(crate) hints_used: u32,
+  // Ticks left before training mode takes over the steering again.
+  pub(crate) takeover_ticks: u64,
+  // Set once the game has ended, after which step does nothing.
+  over: bool,
+  // What the tick step is running has done so far.
+  events: Vec<Event>,
+}
+
+impl GameLogic {
+  pub fn new(difficulty: Difficulty) -> Self {
+    let config = difficulty.config();
+    let seed = config.game_seed();
+    let mut logic = GameLogic {
+      config,
+      board_mode: BoardMode::Classic,
+      play_mode: PlayMode::Single,
+      player: Player::new(config.board().width/2, config.board().height/2),
+      player2: None,
+      player2_score: 0,
+      player2_lives: 0,
+      ai: None,
+      ticks: 0,
+      food: Vec::new(),
+      bonus: None,
+      mobile_food: None,
+      portals: Vec::new(),
+      obstacles: Vec::new(),
+      walls: Vec::new(),
+      moving_walls: Vec::new(),
+      level: 1,
+      level_food: 0,
+      food_eaten: 0,
+      power_up_item: None,
+      power_ups: Vec::new(),
+      rng: RandomNumberGenerator::seeded(seed),
+      score: 0,
+      seed,
+      lives: config.lives,
+      time_bonus: 0,
+      last_food_tick: 0,
+      combo: 1,
+      challenge_next: 0,
+      replay: Vec::new(),
+      replay_pos: 0,
+      replay_hints: Vec::new(),
+      replaying: false,
+      head_path: Vec::new(),
+      trapped: false,
+      hint: None,
+      hint_ticks: 0,
+      hints_used: 0,
+      takeover_ticks: 0,
+      over: false,
+      events: Vec::new(),
+    };
+    logic.spawn_food();
+    logic
+  }
+
+  // Starts a new game straight away, for driving the game with step and
+  // steer and no window.
+  pub fn start(&mut self, play_mode: PlayMode, seed: u64) {
+    self.play_mode = play_mode;
+    self.replaying = false;
+    self.restart(seed);
+  }
+
+  // Everything on the board goes back to how a game starts.
+  pub(crate) fn restart(&mut self, seed: u64) {
+    self.player = self.new_player();
+    self.player2 = match self.play_mode {
+      PlayMode::TwoPlayer => Some(self.new_player2()),
+      _ => None
+    };
+    self.player2_score = 0;
+    self.player2_lives = self.config.lives;
+    self.ai = match self.play_mode {
+      PlayMode::VsAI => Some(self.new_ai()),
+      _ => None
+    };
+    self.ticks = 0;
+    self.obstacles.clear();
+    self.level = 1;
+    self.level_food = 0;
+    self.food_eaten = 0;
+    self.seed = seed;
+    self.rng = RandomNumberGenerator::seeded(self.seed);
+    // After seeding, so a replayed maze comes out the same.
+    self.load_level();
+    self.portals.clear();
+    self.spawn_food();
+    self.spawn_portals();
+    self.spawn_mobile_food();
+    self.bonus = None;
+    self.power_up_item = None;
+    self.power_ups.clear();
+    self.score = 0;
+    self.lives = self.config.lives;
+    self.time_bonus = 0;
+    self.last_food_tick = 0;
+    self.combo = 1;
+    self.challenge_next = 0;
+    self.head_path.clear();
+    self.replay.clear();
+    self.replay_pos = 0;
+    self.replay_hints.clear();
+    self.trapped = false;
+    self.hint = None;
+    self.hint_ticks = 0;
+    self.hints_used = 0;
+    self.takeover_ticks = 0;
+    self.over = false;
+  }
+
+  // A turn or a clicked cell from the first player. A turn lets go of any
+  // clicked cell, and in training mode takes over from the autopilot.
+  pub fn steer(&mut self, turn: Option<Dir>, click: Option<Cell>) {
+    if let Some(dir) = turn.filter(|dir| self.player.queue_turn(*dir)) {
+      self.replay.push((self.ticks, dir));
+      self.takeover_ticks = TAKEOVER_TICKS;
+      self.player.target = None;
+    }
+    if let Some(cell) = click {
+      self.player.target = Some(cell);
+    }
+  }
+
+  // One tick of the game itself.
+  pub fn step(&mut self) -> Vec<Event> {
+    if self.over {
+      return Vec::new();
+    }
+    self.update_replay();
+    // After update_replay, so a replayed hint runs out on the same tick as
+    // the one it was recorded from.
+    self.hint_ticks = self.hint_ticks.saturating_sub(1);
+    if self.hint_ticks == 0 {
+      self.hint = None;
+    }
+    self.takeover_ticks = self.takeover_ticks.saturating_sub(1);
+    if self.ticks.is_multiple_of(self.ticks_per_move()) {
+      self.steer_to_target();
+      self.autopilot();
+      for second in [false, true] {
+        if self.is_alive(second) && self.in_play() {
+          self.move_snake(second);
+        }
+      }
+      self.head_path.push((self.ticks, self.player.head));
+      self.trapped = self.player.is_trapped(&self.player_board());
+      self.hint = if self.hint_ticks > 0 { self.hint_direction() } else { None };
+      if self.ai.is_some() && self.in_play() {
+        self.move_ai();
+      }
+      self.update_obstacles();
+      if self.level_food >= FOOD_PER_LEVEL && self.in_play() {
+        self.next_level();
+      }
+    }
+    if self.play_mode == PlayMode::Survival
+      && self.ticks > 0
+      && self.ticks.is_multiple_of(SURVIVAL_GROWTH_TICKS) {
+      self.player.grow();
+    }
+    if self.play_mode == PlayMode::TimeAttack
+      && self.in_play()
+      && self.ticks >= self.config.time_limit_ticks {
+      self.time_bonus = self.player.cells().len() as i32 * TIME_BONUS_PER_SEGMENT;
+      self.score += self.time_bonus;
+      self.over = true;
+      self.events.push(Event::Survived);
+    }
+    if self.ticks - self.last_food_tick >= COMBO_WINDOW {
+      self.combo = 1;
+    }
+    self.expire_food();
+    self.update_moving_walls();
+    self.update_bonus();
+    self.update_mobile_food();
+    self.update_powerup();
+    self.spawn_power_up();
+    // Only game time is counted, so the clock stops while paused and
+    // the dead screen shows the time at the moment of death.
+    self.ticks += 1;
+    std::mem::take(&mut self.events)
+  }
+
+  // Nothing else moves once this tick has ended the game or the level.
+  fn in_play(&self) -> bool {
+    !self.events.iter().any(|event| matches!(event, Event::Died { .. } | Event::Survived | Event::LevelUp))
+  }
+
+  pub fn is_over(&self) -> bool {
+    self.over
+  }
+
+
+  // Each item gets its own generator seeded from the game seed, so the
+  // whole food sequence still replays from that one seed.
+  fn spawn_food(&mut self) {
+    self.food.clear();
+    let count = match self.play_mode {
+      PlayMode::Challenge => CHALLENGE_COLORS.len(),
+      _ => self.config.food_count
+    };
+    for i in 0..count {
+      let mut taken: Vec<Cell> = self.food.iter().map(|f| f.pos).collect();
+      taken.extend(self.rival_cells());
+      taken.extend(self.walls.iter().copied());
+      let seed = self.seed.wrapping_add(1 + i as u64);
+      let mut food = Food::with_seed(seed, &self.player, &taken, &self.config);
+      food.spawn_tick = self.ticks;
+      self.food.push(food);
+      self.no_challenge_poison(i);
+    }
+  }
+
+  fn respawn_food(&mut self, index: usize) {
+    let others = self.occupied_cells();
+    self.food[index].respawn(&self.player, &others, &self.config);
+    self.food[index].spawn_tick = self.ticks;
+    self.no_challenge_poison(index);
+  }
+
+  fn expire_food(&mut self) {
+    for index in 0..self.food.len() {
+      if self.food[index].ticks_left(self.ticks) == 0 {
+        self.respawn_food(index);
+      }
+    }
+  }
+
+  // Poison would leave a colour missing from the challenge order.
+  fn no_challenge_poison(&mut self, index: usize) {
+    if self.play_mode == PlayMode::Challenge {
+      self.food[index].kind = FoodKind::Normal;
+    }
+  }
+
+  fn spawn_portals(&mut self) {
+    self.portals.clear();
+    for _ in 0..PORTAL_PAIRS {
+      let Some(a) = self.random_free_cell() else { break };
+      let mut blocked = self.occupied_cells();
+      blocked.push(a);
+      let Some(b) = random_free_cell(&mut self.rng, &self.config, |cell| !blocked.contains(cell)) else { break };
+      self.portals.push(Portal { a, b });
+    }
+  }
+
+  fn food_at(&self, cell: Cell) -> Option<usize> {
+    self.food.iter().position(|f| f.pos == cell)
+  }
+
+  // Every cell something is drawn on, for keeping new items apart.
+  pub(crate) fn occupied_cells(&self) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = self.player.tail.iter().copied().collect();
+    cells.push(self.player.head);
+    cells.extend(self.rival_cells());
+    cells.extend(self.food.iter().map(|f| f.pos));
+    cells.extend(self.bonus.iter().map(|b| b.pos));
+    cells.extend(self.mobile_food.iter().map(|m| m.pos));
+    for portal in self.portals.iter() {
+      cells.push(portal.a);
+      cells.push(portal.b);
+    }
+    cells.extend(self.obstacles.iter().copied());
+    cells.extend(self.walls.iter().copied());
+    cells.extend(self.moving_wall_cells());
+    cells.extend(self.power_up_item.iter().map(|(_, pos)| *pos));
+    cells
+  }
+
+  // The configured length, cut down so the tail still fits between a head
+  // at column x and the right hand wall.
+  fn initial_length(&self, x: i32) -> usize {
+    let room = (self.config.board().width - x).max(1) as usize;
+    self.config.initial_length.clamp(1, room)
+  }
+
+  fn new_player(&self) -> Player {
+    let x = self.config.board().width/2;
+    let mut player = Player::new_with_length(x, self.config.board().height/2, self.initial_length(x), Dir::Static);
+    player.color = RGB::named(self.config.theme.colors().snake_head);
+    if self.play_mode == PlayMode::TwoPlayer {
+      player.controls = Controls::Wasd;
+    }
+    player
+  }
+
+  fn new_player2(&self) -> Player {
+    let x = self.config.board().width/2;
+    let mut player = Player::new_with_length(x, self.config.board().height/4, self.initial_length(x), Dir::Static);
+    player.controls = Controls::Arrows;
+    player.color = RGB::named(ORANGE);
+    player
+  }
+
+  fn new_ai(&self) -> AiPlayer {
+    let x = self.config.board().width/2;
+    AiPlayer::new(x, self.config.board().height*3/4, self.initial_length(x))
+  }
+
+  fn player2_cells(&self) -> Vec<Cell> {
+    match &self.player2 {
+      Some(p) if self.player2_lives > 0 => p.cells(),
+      _ => Vec::new()
+    }
+  }
+
+  fn ai_cells(&self) -> Vec<Cell> {
+    self.ai.as_ref().map(|ai| ai.player.cells()).unwrap_or_default()
+  }
+
+  // Every snake other than player 1's.
+  fn rival_cells(&self) -> Vec<Cell> {
+    let mut cells = self.player2_cells();
+    cells.extend(self.ai_cells());
+    cells
+  }
+
+  // Every snake on the board apart from the given player's.
+  fn other_snakes(&self, second: bool) -> Vec<&Player> {
+    let mut snakes: Vec<&Player> = self.ai.iter().map(|ai| &ai.player).collect();
+    if second {
+      snakes.push(&self.player);
+    } else if self.player2_lives > 0 {
+      snakes.extend(self.player2.as_ref());
+    }
+    snakes
+  }
+
+  pub(crate) fn is_alive(&self, second: bool) -> bool {
+    if second {
+      self.player2.is_some() && self.player2_lives > 0
+    } else {
+      self.lives > 0
+    }
+  }
+
+  pub(crate) fn snake(&self, second: bool) -> &Player {
+    match self.player2.as_ref() {
+      Some(p) if second => p,
+      _ => &self.player
+    }
+  }
+
+  fn snake_mut(&mut self, second: bool) -> &mut Player {
+    match self.player2.as_mut() {
+      Some(p) if second => p,
+      _ => &mut self.player
+    }
+  }
+
+  fn update_obstacles(&mut self) {
+    // A stray obstacle could cut a maze in two.
+    if self.board_mode == BoardMode::Maze {
+      return;
+    }
+    let batches = (self.plain_score() / POINTS_PER_OBSTACLES).max(0) as usize;
+    let reserved = self.spawn_cells();
+    while self.obstacles.len() < self.config.initial_obstacles + batches * OBSTACLE_BATCH {
+      let mut blocked = self.occupied_cells();
+      blocked.extend(reserved.iter().copied());
+      match random_free_cell(&mut self.rng, &self.config, |cell| !blocked.contains(cell)) {
+        Some(cell) => self.obstacles.push(cell),
+        None => break
+      }
+    }
+  }
+
+  // Kept clear of obstacles and walls so losing a life never lands on one.
+  fn spawn_cells(&self) -> Vec<Cell> {
+    [self.new_player(), self.new_player2(), self.new_ai().player].iter()
+      .flat_map(|start| start.cells())
+      .collect()
+  }
+
+  fn load_level(&mut self) {
+    let layout = match self.board_mode {
+      BoardMode::Maze => self.generate_maze(),
+      _ => self.config.board().fit_layout(LEVELS[(self.level as usize - 1) % LEVELS.len()])
+    };
+    let reserved = self.spawn_cells();
+    let board = self.config.board();
+    self.walls = layout.into_iter()
+      .filter(|cell| board.contains(*cell) && !reserved.contains(cell))
+      .collect();
+    self.load_moving_walls();
+  }
+
+  // Kept to rows no snake spawns on, the first along the top heading right
+  // and the second along the bottom heading left. Mazes have none, since a
+  // bar would block a passage.
+  fn load_moving_walls(&mut self) {
+    self.moving_walls.clear();
+    if self.board_mode == BoardMode::Maze || self.level < MOVING_WALL_LEVEL {
+      return;
+    }
+    let spawn_rows: Vec<i32> = self.spawn_cells().iter().map(|cell| cell.y).collect();
+    let clear = |y: &i32| (*y..*y + MOVING_WALL_LENGTH).all(|row| !spawn_rows.contains(&row));
+    let rows = 0..self.config.board().height - MOVING_WALL_LENGTH;
+    if let Some(y) = rows.clone().find(clear) {
+      self.moving_walls.push(MovingWall::new(0, y, Dir::Right));
+    }
+    if self.config.difficulty == Difficulty::Insane {
+      if let Some(y) = rows.rev().find(clear) {
+        self.moving_walls.push(MovingWall::new(self.config.board().width - 2, y, Dir::Left));
+      }
+    }
+  }
+
+  // The fixed and moving walls and obstacles, plus the given snake cells.
+  fn board_state(&self, snakes: Vec<Cell>) -> BoardState {
+    let blocked = snakes.into_iter()
+      .chain(self.obstacles.iter().copied())
+      .chain(self.walls.iter().copied())
+      .chain(self.moving_wall_cells());
+    BoardState::new(&self.config, self.board_mode, blocked)
+  }
+
+  fn moving_wall_cells(&self) -> Vec<Cell> {
+    self.moving_walls.iter().flat_map(|wall| wall.cells.iter().copied()).collect()
+  }
+
+  // A wall sliding onto a snake crashes it, and food it lands on moves.
+  fn update_moving_walls(&mut self) {
+    let mut blocked = self.obstacles.clone();
+    blocked.extend(self.walls.iter().copied());
+    let (ticks, config) = (self.ticks, self.config);
+    let mut moved = false;
+    for wall in self.moving_walls.iter_mut() {
+      moved |= wall.update(ticks, &blocked, &config);
+    }
+    if !moved {
+      return;
+    }
+    let cells = self.moving_wall_cells();
+    for second in [false, true] {
+      let snake = if second { self.player2.as_ref() } else { Some(&self.player) };
+      let hit = snake.is_some_and(|snake| cells.iter().any(|cell| snake.occupies(*cell)));
+      if hit && self.is_alive(second) && self.in_play() {
+        self.crash(second);
+      }
+    }
+    if self.ai.as_ref().is_some_and(|ai| cells.iter().any(|cell| ai.player.occupies(*cell))) {
+      self.ai = Some(self.new_ai());
+    }
+    for index in 0..self.food.len() {
+      if cells.contains(&self.food[index].pos) {
+        self.respawn_food(index);
+      }
+    }
+  }
+
+  // A recursive backtracker carving passages between rooms on the even
+  // cells, so every open cell ends up connected. Snakes spawn in the middle
+  // of the board, which can be an odd/odd pillar boxed in by walls, so each
+  // spawn cell is carved too, along with the cell above it on an odd row,
+  // which is a room or a passage between two.
+  fn generate_maze(&mut self) -> Vec<Cell> {
+    let board = self.config.board();
+    let index = |cell: Cell| (cell.y * board.width + cell.x) as usize;
+    let mut open = vec![false; (board.width * board.height) as usize];
+    let start = Cell::new(0, 0);
+    open[index(start)] = true;
+    let mut path = vec![start];
+    while let Some(&room) = path.last() {
+      let unvisited: Vec<Dir> = [Dir::Up, Dir::Down, Dir::Left, Dir::Right].iter()
+        .copied()
+        .filter(|dir| {
+          let next = Cell::step(Cell::step(room, *dir), *dir);
+          board.contains(next) && !open[index(next)]
+        })
+        .collect();
+      if unvisited.is_empty() {
+        path.pop();
+        continue;
+      }
+      let dir = unvisited[self.rng.range(0, unvisited.len())];
+      let passage = Cell::step(room, dir);
+      let next = Cell::step(passage, dir);
+      open[index(passage)] = true;
+      open[index(next)] = true;
+      path.push(next);
+    }
+    for cell in self.spawn_cells().into_iter().filter(|cell| board.contains(*cell)) {
+      open[index(cell)] = true;
+      if cell.y % 2 == 1 {
+        open[index(Cell::new(cell.x, cell.y - 1))] = true;
+      }
+    }
+    board.cells()
+      .filter(|cell| !open[index(*cell)])
+      .collect()
+  }
+
+  // Every snake goes back to its spawn point and everything else on the
+  // board is placed again around the new walls. The score carries over.
+  fn next_level(&mut self) {
+    self.level += 1;
+    self.level_food = 0;
+    let distance = self.player.total_distance;
+    self.player = self.new_player();
+    self.player.total_distance = distance;
+    if let Some(distance) = self.player2.as_ref().map(|p| p.total_distance) {
+      let mut player2 = self.new_player2();
+      player2.total_distance = distance;
+      self.player2 = Some(player2);
+    }
+    if self.ai.is_some() {
+      self.ai = Some(self.new_ai());
+    }
+    self.load_level();
+    self.events.push(Event::LevelUp);
+    self.bonus = None;
+    self.power_up_item = None;
+    for index in 0..self.food.len() {
+      self.respawn_food(index);
+    }
+    self.spawn_portals();
+    self.spawn_mobile_food();
+  }
+
+  // Counts down the active power-ups and drops the ones that ran out.
+  fn update_powerup(&mut self) {
+    for (_, _, ticks_remaining) in self.power_ups.iter_mut() {
+      *ticks_remaining = ticks_remaining.saturating_sub(1);
+    }
+    self.power_ups.retain(|(_, _, ticks_remaining)| *ticks_remaining > 0);
+    let invincible = |second| self.power_ups.iter()
+      .any(|(kind, owner, _)| *kind == PowerUpKind::Invincible && *owner == Some(second));
+    let (first, second) = (invincible(false), invincible(true));
+    self.player.invincible = first;
+    if let Some(p) = self.player2.as_mut() {
+      p.invincible = second;
+    }
+  }
+
+  fn spawn_power_up(&mut self) {
+    if self.power_up_item.is_none() && self.ticks > 0 && self.ticks.is_multiple_of(POWER_UP_INTERVAL) {
+      let occupied = self.occupied_cells();
+      let head = self.player.head;
+      let is_free = |cell: &Cell| {
+        !occupied.contains(cell)
+          && cell.manhattan_distance(&head) > POWER_UP_MIN_DISTANCE
+      };
+      let kind = PowerUpKind::random(&mut self.rng);
+      self.power_up_item = random_free_cell(&mut self.rng, &self.config, is_free)
+        .map(|pos| (kind, pos));
+    }
+  }
+
+  fn random_free_cell(&mut self) -> Option<Cell> {
+    let occupied = self.occupied_cells();
+    random_free_cell(&mut self.rng, &self.config, |cell| !occupied.contains(cell))
+  }
+
+  fn spawn_mobile_food(&mut self) {
+    self.mobile_food = None;
+    if let Some(pos) = self.random_free_cell() {
+      self.mobile_food = Some(MobileFood::new(pos, &mut self.rng));
+    }
+  }
+
+  fn update_mobile_food(&mut self) {
+    let Some(interval) = self.mobile_food.as_ref().map(|m| m.move_interval) else { return };
+    if !self.ticks.is_multiple_of(interval) {
+      return;
+    }
+    let blocked = self.occupied_cells();
+    let config = self.config;
+    if let Some(mobile) = self.mobile_food.as_mut() {
+      mobile.update(&blocked, &config);
+    }
+  }
+
+  fn update_bonus(&mut self) {
+    if let Some(bonus) = self.bonus.as_mut() {
+      bonus.ticks_remaining = bonus.ticks_remaining.saturating_sub(1);
+      if bonus.ticks_remaining == 0 {
+        self.bonus = None;
+      }
+    } else if self.ticks > 0 && self.ticks.is_multiple_of(BONUS_INTERVAL) {
+      self.bonus = self.random_free_cell().map(BonusFood::new);
+    }
+  }
+
+  // Only one hint runs at a time, and none on Hard or Insane.
+  pub(crate) fn use_hint(&mut self) {
+    if !self.config.difficulty.allows_hints() || self.hint_ticks > 0 {
+      return;
+    }
+    self.score = (self.score - HINT_COST).max(0);
+    self.hints_used += 1;
+    self.hint_ticks = HINT_TICKS;
+    self.hint = self.hint_direction();
+    if !self.replaying {
+      self.replay_hints.push(self.ticks);
+    }
+  }
+
+  // Steers the first player along the shortest path to food in training
+  // mode, unless they have taken over.
+  fn autopilot(&mut self) {
+    if self.play_mode != PlayMode::Training || self.takeover_ticks > 0 || !self.player.inputs.is_empty() {
+      return;
+    }
+    if let Some(dir) = self.path_to_food() {
+      self.player.inputs.push_back(dir);
+    }
+  }
+
+  fn path_to_food(&self) -> Option<Dir> {
+    let head = self.player.head;
+    let mut targets: Vec<Cell> = self.food.iter()
+      .filter(|f| f.kind == FoodKind::Normal)
+      .map(|f| f.pos)
+      .collect();
+    targets.sort_by_key(|target| head.manhattan_distance(target));
+    let board = self.player_board();
+    targets.iter().find_map(|target| self.player.shortest_path_to(*target, &board))
+  }
+
+  // The way to the nearest food, or failing that any move that doesn't
+  // crash straight away.
+  fn hint_direction(&self) -> Option<Dir> {
+    self.path_to_food().or_else(|| {
+      let board = self.player_board();
+      let player = &self.player;
+      [Dir::Up, Dir::Down, Dir::Left, Dir::Right].iter().copied()
+        .filter(|dir| !dir.is_opposite(player.dir))
+        .find(|dir| player.can_move_in(*dir, &board.board) && !board.blocked.contains(&Cell::step(player.head, *dir)))
+    })
+  }
+
+  // What the first player has to steer around.
+  fn player_board(&self) -> BoardState {
+    let rivals = self.other_snakes(false).iter().flat_map(|snake| snake.cells()).collect();
+    self.board_state(rivals)
+  }
+
+  // Turns the first player towards a clicked cell, letting go of it once
+  // the head is next to it or there is no way there. The turns are
+  // recorded like pressed ones so the game still replays.
+  fn steer_to_target(&mut self) {
+    let Some(target) = self.player.target else { return };
+    if self.player.head.manhattan_distance(&target) <= 1 {
+      self.player.target = None;
+      return;
+    }
+    if !self.player.inputs.is_empty() {
+      return;
+    }
+    match self.player.shortest_path_to(target, &self.player_board()) {
+      Some(dir) => {
+        self.player.inputs.push_back(dir);
+        self.replay.push((self.ticks, dir));
+      }
+      None => self.player.target = None
+    }
+  }
+
+  fn update_replay(&mut self) {
+    if self.replaying {
+      while let Some(&(tick, dir)) = self.replay.get(self.replay_pos) {
+        if tick > self.ticks {
+          break;
+        }
+        self.player.inputs.push_back(dir);
+        self.replay_pos += 1;
+      }
+      if self.replay_hints.contains(&self.ticks) {
+        self.use_hint();
+      }
+    }
+  }
+
+  // Moves one snake a cell and resolves whatever its head ran into.
+  fn move_snake(&mut self, second: bool) {
+    let board_mode = self.board_mode;
+    let config = self.config;
+    let survival = self.play_mode == PlayMode::Survival;
+    let portals = self.portals.clone();
+    let snake = self.snake_mut(second);
+    snake.update_position();
+    // Walls and collisions are checked where the snake comes out.
+    if let Some(exit) = portals.iter().find_map(|portal| portal.exit_for(snake.head)) {
+      snake.head = exit;
+    }
+    let hit_wall = snake.check_walls(board_mode, &config);
+    let head = snake.head;
+    let hit_self = snake.check_self_collision();
+    let snake = self.snake(second);
+    let moving_walls = self.moving_wall_cells();
+    let hit_obstacle = self.obstacles.iter().chain(&self.walls).chain(&moving_walls)
+      .any(|cell| snake.occupies(*cell));
+    let hit_rival = self.other_snakes(second).iter().any(|rival| rival.occupies(head));
+    if hit_self || hit_wall || hit_obstacle || hit_rival {
+      self.crash(second);
+    } else if let Some(eaten) = self.food_at(head).filter(|_| self.play_mode == PlayMode::Challenge) {
+      self.challenge_play(second, eaten);
+    } else if let Some(eaten) = self.food_at(head) {
+      let kind = self.food[eaten].kind;
+      let snake = self.snake_mut(second);
+      match kind {
+        FoodKind::Normal => {
+          if !survival {
+            snake.grow();
+          }
+          self.score_food(second, Meal::Food(kind));
+        }
+        FoodKind::Poison if snake.tail.is_empty() => self.crash(second),
+        FoodKind::Poison => snake.shrink(),
+      }
+      self.respawn_food(eaten);
+    } else if self.mobile_food.as_ref().is_some_and(|m| m.pos == head) {
+      if !survival {
+        self.snake_mut(second).grow();
+      }
+      self.score_food(second, Meal::Mobile);
+      self.spawn_mobile_food();
+    } else if self.bonus.take_if(|b| b.pos == head).is_some() {
+      let points = self.score_for_food(second, Meal::Bonus);
+      self.add_points(second, points);
+    } else if self.power_up_item.take_if(|(kind, pos)| *pos == head && *kind == PowerUpKind::Teleport).is_some() {
+      if let Some(cell) = self.random_free_cell() {
+        self.snake_mut(second).head = cell;
+      }
+    } else if let Some((kind, _)) = self.power_up_item.take_if(|(_, pos)| *pos == head) {
+      // Picking up one that is already running just restarts its timer.
+      let owner = (kind == PowerUpKind::Invincible).then_some(second);
+      self.power_ups.retain(|(active, active_owner, _)| (*active, *active_owner) != (kind, owner));
+      self.power_ups.push((kind, owner, kind.duration()));
+    }
+  }
+
+  // The right colour scores and grows as usual and moves the target on,
+  // any other costs points. Either way the food moves somewhere else.
+  fn challenge_play(&mut self, second: bool, eaten: usize) {
+    if eaten == self.challenge_next {
+      self.snake_mut(second).grow();
+      self.score_food(second, Meal::Food(FoodKind::Normal));
+      self.challenge_next = (self.challenge_next + 1) % CHALLENGE_COLORS.len();
+    } else {
+      let score = if second { &mut self.player2_score } else { &mut self.score };
+      *score = (*score - CHALLENGE_PENALTY).max(0);
+    }
+    self.respawn_food(eaten);
+  }
+
+  pub fn food_eaten(&self) -> u32 {
+    self.food_eaten
+  }
+
+  pub fn score(&self) -> i32 {
+    self.score
+  }
+
+  pub fn lives(&self) -> u8 {
+    self.lives
+  }
+
+  pub fn player(&self) -> &Player {
+    &self.player
+  }
+
+  // What eating kind is worth to a snake, times the difficulty's
+  // multiplier. Normal and mobile food get the combo, already counting this
+  // one, and the straight bonus. A bonus item is worth its fixed points and
+  // poison is never scored.
+  fn score_for_food(&self, second: bool, kind: Meal) -> i32 {
+    let points = match kind {
+      Meal::Food(FoodKind::Poison) => 0,
+      Meal::Bonus => BONUS_POINTS,
+      Meal::Food(FoodKind::Normal) | Meal::Mobile => {
+        let straight_bonus = (1 + self.snake(second).straight_moves / STRAIGHT_MOVES_PER_BONUS).min(MAX_STRAIGHT_BONUS);
+        FOOD_POINTS * (self.combo * straight_bonus) as i32
+      }
+    };
+    points * self.config.difficulty.score_multiplier()
+  }
+
+  // The score before the difficulty's multiplier, which the speed-ups and
+  // obstacles go by so every difficulty gets harder at the same pace.
+  fn plain_score(&self) -> i32 {
+    self.score / self.config.difficulty.score_multiplier()
+  }
+
+  fn add_points(&mut self, second: bool, points: i32) {
+    if second {
+      self.player2_score += points;
+    } else {
+      self.score += points;
+    }
+  }
+
+  fn score_food(&mut self, second: bool, kind: Meal) {
+    if self.ticks - self.last_food_tick < COMBO_WINDOW {
+      self.combo = (self.combo + 1).min(MAX_COMBO);
+    } else {
+      self.combo = 1;
+    }
+    self.last_food_tick = self.ticks;
+    self.level_food += 1;
+    self.food_eaten += 1;
+    let head = self.snake(second).head;
+    self.events.push(Event::Ate(head));
+    let points = self.score_for_food(second, kind);
+    self.add_points(second, points);
+  }
+
+  fn move_ai(&mut self) {
+    let targets: Vec<Cell> = self.food.iter()
+      .filter(|f| f.kind == FoodKind::Normal)
+      .map(|f| f.pos)
+      .collect();
+    let board = self.board_state(self.player.cells());
+    let (board_mode, config) = (self.board_mode, self.config);
+    let portals = self.portals.clone();
+    let Some(ai) = self.ai.as_mut() else { return };
+    ai.choose_direction(&targets, &board);
+    let snake = &mut ai.player;
+    snake.update_position();
+    if let Some(exit) = portals.iter().find_map(|portal| portal.exit_for(snake.head)) {
+      snake.head = exit;
+    }
+    let hit_wall = snake.check_walls(board_mode, &config);
+    let head = snake.head;
+    let crashed = hit_wall || snake.check_self_collision() || board.blocked.contains(&head);
+    if crashed {
+      // The computer never runs out of lives, it just starts over.
+      self.ai = Some(self.new_ai());
+    } else if let Some(eaten) = self.food_at(head) {
+      let kind = self.food[eaten].kind;
+      if let Some(ai) = self.ai.as_mut() {
+        match kind {
+          FoodKind::Normal => ai.player.grow(),
+          FoodKind::Poison => ai.player.shrink(),
+        }
+      }
+      // Every food the computer gets to first costs the player what it
+      // would have scored them.
+      let points = self.score_for_food(false, Meal::Food(kind));
+      self.score = (self.score - points).max(0);
+      self.respawn_food(eaten);
+    }
+  }
+
+  fn ticks_per_move(&self) -> u64 {
+    let speedup = self.config.initial_speed + (self.plain_score() / POINTS_PER_SPEEDUP).max(0) as u64;
+    let interval = self.config.ticks_per_move.saturating_sub(speedup);
+    // Apply all doublings before any halving so a speed boost and a slow
+    // motion together cancel out exactly.
+    let count = |kind| self.power_ups.iter().filter(|(active, _, _)| *active == kind).count() as u32;
+    let slowed = interval << count(PowerUpKind::SlowMotion);
+    (slowed >> count(PowerUpKind::SpeedBoost)).max(1)
+  }
+
+  fn elapsed_seconds(&self) -> f64 {
+    self.ticks as f64 / TICKS_PER_SECOND as f64
+  }
+
+  pub(crate) fn elapsed_time(&self) -> String {
+    let seconds = self.elapsed_seconds() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+  }
+
+  // Zen games never end, a crash only costs tail.
+  fn crash(&mut self, second: bool) {
+    if self.board_mode == BoardMode::Zen {
+      self.snake_mut(second).cut_at_head();
+    } else {
+      self.lose_life(second);
+    }
+  }
+
+  fn lose_life(&mut self, second: bool) {
+    let lives = if second { &mut self.player2_lives } else { &mut self.lives };
+    *lives = lives.saturating_sub(1);
+    self.events.push(Event::LostLife);
+    if !self.is_alive(false) && !self.is_alive(true) {
+      // The snake that crashed last is left as it was, for the dying
+      // animation and the dead screen.
+      self.over = true;
+      self.events.push(Event::Died { second });
+      return;
+    }
+    if !self.is_alive(second) {
+      return;
+    }
+    // Start over from the spawn point with no tail, the score is kept.
+    let mut fresh = if second { self.new_player2() } else { self.new_player() };
+    fresh.tail.clear();
+    fresh.total_distance = self.snake_mut(second).total_distance;
+    let head = fresh.head;
+    *self.snake_mut(second) = fresh;
+    if let Some(covered) = self.food_at(head) {
+      self.respawn_food(covered);
+    }
+  }
+
+  // A replay is the same game over again, so it is not counted twice. A
+  // training game was mostly played by the autopilot, so it is not counted
+  // at all.
+  pub(crate) fn is_recorded(&self) -> bool {
+    !self.replaying && self.play_mode != PlayMode::Training
+  }}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn moving_onto_food_eats_it() {
+    let mut logic = GameLogic::new(Difficulty::Normal);
+    logic.start(PlayMode::Training, 1);
+    let head = logic.player.head;
+    let length = logic.player.len();
+    // The tail starts off to the right, so the food goes on the left.
+    logic.food = vec![Food::new_at(head.x - 1, head.y)];
+    logic.player.inputs.push_back(Dir::Left);
+    logic.move_snake(false);
+    assert_eq!(logic.player.head, Cell::new(head.x - 1, head.y));
+    assert_eq!(logic.food_eaten(), 1);
+    assert_eq!(logic.player.len(), length + 1);
+    assert_ne!(logic.food[0].pos, logic.player.head);
+  }
+
+  #[test]
+  fn invincibility_only_protects_the_snake_that_picked_it_up() {
+    let mut logic = GameLogic::new(Difficulty::Normal);
+    logic.start(PlayMode::TwoPlayer, 1);
+    let head = logic.player2.as_ref().unwrap().head;
+    // Both snakes start with the tail off to the right.
+    logic.power_up_item = Some((PowerUpKind::Invincible, Cell::new(head.x - 1, head.y)));
+    logic.player2.as_mut().unwrap().inputs.push_back(Dir::Left);
+    logic.move_snake(true);
+    logic.update_powerup();
+    assert!(logic.player2.as_ref().unwrap().invincible);
+    assert!(!logic.player.invincible);
+  }
+
+  #[test]
+  fn losing_the_last_life_ends_the_game() {
+    let mut logic = GameLogic::new(Difficulty::Normal);
+    logic.config.lives = 1;
+    logic.start(PlayMode::Single, 1);
+    logic.steer(Some(Dir::Up), None);
+    let mut events = Vec::new();
+    while !logic.is_over() {
+      events.extend(logic.step());
+    }
+    assert_eq!(events.last(), Some(&Event::Died { second: false }));
+    assert!(events.contains(&Event::LostLife));
+    let ticks = logic.ticks;
+    assert!(logic.step().is_empty());
+    assert_eq!(logic.ticks, ticks);
+  }
+
+  #[test]
+  fn food_scores_combo_times_straight_bonus_times_difficulty() {
+    let mut logic = GameLogic::new(Difficulty::Hard);
+    logic.start(PlayMode::Single, 1);
+    logic.combo = 3;
+    logic.player.straight_moves = 2 * STRAIGHT_MOVES_PER_BONUS;
+    let multiplier = Difficulty::Hard.score_multiplier();
+    assert_eq!(logic.score_for_food(false, Meal::Food(FoodKind::Normal)), FOOD_POINTS * 3 * 3 * multiplier);
+    assert_eq!(logic.score_for_food(false, Meal::Mobile), FOOD_POINTS * 3 * 3 * multiplier);
+    assert_eq!(logic.score_for_food(false, Meal::Bonus), BONUS_POINTS * multiplier);
+    assert_eq!(logic.score_for_food(false, Meal::Food(FoodKind::Poison)), 0);
+    logic.player.straight_moves = 100 * STRAIGHT_MOVES_PER_BONUS;
+    assert_eq!(logic.score_for_food(false, Meal::Mobile), FOOD_POINTS * 3 * MAX_STRAIGHT_BONUS as i32 * multiplier);
+  }
+
+  #[test]
+  fn maze_food_is_reachable_from_every_spawn_point() {
+    // A one-cell snake leaves no tail to open a way out of a pillar cell,
+    // and seed 42 walls the middle pillar in without the carving.
+    for (seed, initial_length) in (0..50).zip([1, 3].iter().cycle()) {
+      let mut logic = GameLogic::new(Difficulty::Normal);
+      logic.config.initial_length = *initial_length;
+      logic.board_mode = BoardMode::Maze;
+      logic.start(PlayMode::VsAI, seed);
+      let board = logic.config.board();
+      let mut reached = HashSet::new();
+      let mut queue = VecDeque::from(vec![logic.player.head]);
+      while let Some(cell) = queue.pop_front() {
+        if board.contains(cell) && !logic.walls.contains(&cell) && reached.insert(cell) {
+          for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
+            queue.push_back(Cell::step(cell, dir));
+          }
+        }
+      }
+      assert!(logic.food.iter().any(|food| reached.contains(&food.pos)), "seed {}", seed);
+      assert!(reached.contains(&logic.ai.as_ref().unwrap().player.head), "seed {}", seed);
+    }
+  }
+}
@@ -0,0 +1,218 @@
+use super::Cell;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::iter::FromIterator;
+
+// The tail behind the ring-tail feature. benches/tail.rs runs the same
+// operations on it and on a VecDeque, cargo bench --features ring-tail
+// --bench tail. On a 100 cell tail:
+//
+//   a move, push_front and pop_back   VecDeque 3.7ns  RingTail 9.3ns
+//   growing a new tail to 500 cells   VecDeque 1.5us  RingTail 3.5us
+//   contains, on the last cell        VecDeque 85ns   RingTail 53ns
+//   clone                             VecDeque 30ns   RingTail 126ns
+//
+// and the update_position bench goes from 5.2ns to 11.4ns with the feature
+// on, while occupies shows no clear change. VecDeque is a ring buffer too.
+// It only allocates when the tail doubles, a handful of times a game, and
+// its clone copies just the cells in use where RingTail copies its whole
+// capacity, which the AI's lookahead pays for on every move it tries.
+// RingTail only wins the linear search, by less than the moves cost it, so
+// the VecDeque stays the default and this stays behind the feature for
+// comparison.
+
+// Room for every cell of a 24x24 board, which covers all the boards the
+// settings screen offers at the default screen size apart from Huge.
+const TAIL_CAPACITY : usize = 24 * 24;
+
+// A ring buffer allocated once with TAIL_CAPACITY cells, so growing and
+// moving never allocate. A snake that outgrows it, on a bigger board, has
+// its storage doubled like a Vec would.
+#[derive(Debug, Clone)]
+pub struct RingTail {
+  // Always full length. The tail is the len cells from start on, wrapping
+  // round to the beginning.
+  cells: Vec<Cell>,
+  start: usize,
+  len: usize
+}
+
+impl RingTail {
+  pub fn new() -> Self {
+    RingTail::with_capacity(TAIL_CAPACITY)
+  }
+
+  pub fn with_capacity(capacity: usize) -> Self {
+    RingTail { cells: vec![Cell::new(0, 0); capacity.max(1)], start: 0, len: 0 }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn clear(&mut self) {
+    self.len = 0;
+  }
+
+  pub fn truncate(&mut self, len: usize) {
+    self.len = self.len.min(len);
+  }
+
+  // index is never more than a lap past start, so one subtraction wraps it
+  // instead of a division.
+  fn slot(&self, index: usize) -> usize {
+    let slot = self.start + index;
+    if slot >= self.cells.len() { slot - self.cells.len() } else { slot }
+  }
+
+  pub fn front(&self) -> Option<&Cell> {
+    self.as_slices().0.first()
+  }
+
+  pub fn back(&self) -> Option<&Cell> {
+    let (front, back) = self.as_slices();
+    back.last().or(front.last())
+  }
+
+  pub fn push_front(&mut self, cell: Cell) {
+    self.make_room();
+    self.start = self.slot(self.cells.len() - 1);
+    self.cells[self.start] = cell;
+    self.len += 1;
+  }
+
+  pub fn push_back(&mut self, cell: Cell) {
+    self.make_room();
+    let slot = self.slot(self.len);
+    self.cells[slot] = cell;
+    self.len += 1;
+  }
+
+  pub fn pop_back(&mut self) -> Option<Cell> {
+    if self.len == 0 {
+      return None;
+    }
+    self.len -= 1;
+    Some(self.cells[self.slot(self.len)])
+  }
+
+  pub fn contains(&self, cell: &Cell) -> bool {
+    let (front, back) = self.as_slices();
+    front.contains(cell) || back.contains(cell)
+  }
+
+  pub fn iter(&self) -> std::iter::Chain<std::slice::Iter<'_, Cell>, std::slice::Iter<'_, Cell>> {
+    let (front, back) = self.as_slices();
+    front.iter().chain(back.iter())
+  }
+
+  pub fn iter_mut(&mut self) -> std::iter::Chain<std::slice::IterMut<'_, Cell>, std::slice::IterMut<'_, Cell>> {
+    let end = self.start + self.len;
+    let capacity = self.cells.len();
+    let (wrapped, rest) = self.cells.split_at_mut(self.start);
+    let (front, back): (&mut [Cell], &mut [Cell]) = if end <= capacity {
+      (&mut rest[..self.len], Default::default())
+    } else {
+      (rest, &mut wrapped[..end - capacity])
+    };
+    front.iter_mut().chain(back.iter_mut())
+  }
+
+  // The tail from start to the end of the storage, then whatever wrapped
+  // round.
+  fn as_slices(&self) -> (&[Cell], &[Cell]) {
+    let end = self.start + self.len;
+    let capacity = self.cells.len();
+    if end <= capacity {
+      (&self.cells[self.start..end], &[])
+    } else {
+      (&self.cells[self.start..], &self.cells[..end - capacity])
+    }
+  }
+
+  fn make_room(&mut self) {
+    if self.len == self.cells.len() {
+      let mut cells: Vec<Cell> = self.iter().copied().collect();
+      cells.resize(self.len * 2, Cell::new(0, 0));
+      self.cells = cells;
+      self.start = 0;
+    }
+  }
+}
+
+impl Default for RingTail {
+  fn default() -> Self {
+    RingTail::new()
+  }
+}
+
+impl PartialEq for RingTail {
+  fn eq(&self, other: &Self) -> bool {
+    self.len == other.len && self.iter().eq(other.iter())
+  }
+}
+
+impl FromIterator<Cell> for RingTail {
+  fn from_iter<I: IntoIterator<Item = Cell>>(cells: I) -> Self {
+    let mut tail = RingTail::new();
+    for cell in cells {
+      tail.push_back(cell);
+    }
+    tail
+  }
+}
+
+impl<const N: usize> From<[Cell; N]> for RingTail {
+  fn from(cells: [Cell; N]) -> Self {
+    IntoIterator::into_iter(cells).collect()
+  }
+}
+
+impl<'a> IntoIterator for &'a RingTail {
+  type Item = &'a Cell;
+  type IntoIter = std::iter::Chain<std::slice::Iter<'a, Cell>, std::slice::Iter<'a, Cell>>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter()
+  }
+}
+
+// Saved and loaded as a plain list, the same as the VecDeque, so a state
+// dump reads back with either.
+impl Serialize for RingTail {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(self.iter())
+  }
+}
+
+impl<'de> Deserialize<'de> for RingTail {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    Vec::<Cell>::deserialize(deserializer).map(|cells| cells.into_iter().collect())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn wraps_round_and_grows_past_its_capacity() {
+    let mut tail = RingTail::with_capacity(2);
+    tail.push_back(Cell::new(1, 0));
+    tail.push_front(Cell::new(0, 0));
+    tail.push_front(Cell::new(-1, 0));
+    tail.push_back(Cell::new(2, 0));
+    let cells: Vec<Cell> = tail.iter().copied().collect();
+    assert_eq!(cells, [Cell::new(-1, 0), Cell::new(0, 0), Cell::new(1, 0), Cell::new(2, 0)]);
+    assert_eq!(tail.pop_back(), Some(Cell::new(2, 0)));
+    tail.push_front(Cell::new(-2, 0));
+    for cell in tail.iter_mut() {
+      cell.y = 1;
+    }
+    assert_eq!(tail, RingTail::from([Cell::new(-2, 1), Cell::new(-1, 1), Cell::new(0, 1), Cell::new(1, 1)]));
+    assert_eq!(tail.back(), Some(&Cell::new(1, 1)));
+  }
+}
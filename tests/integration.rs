@@ -0,0 +1,53 @@
+use snek::{Dir, Difficulty, Event, GameLogic, PlayMode};
+
+// Training games steer themselves, so they can run here on the game logic
+// alone, which has no window and never reads or writes the player's files.
+fn training(seed: u64) -> GameLogic {
+  let mut logic = GameLogic::new(Difficulty::Normal);
+  logic.start(PlayMode::Training, seed);
+  logic
+}
+
+// Everything the ticks did, stopping early if the game ends.
+fn run(logic: &mut GameLogic, ticks: usize) -> Vec<Event> {
+  let mut events = Vec::new();
+  for _ in 0..ticks {
+    if logic.is_over() {
+      break;
+    }
+    events.extend(logic.step());
+  }
+  events
+}
+
+#[test]
+fn autopilot_eats_food() {
+  let mut logic = training(1);
+  let events = run(&mut logic, 2000);
+  assert!(logic.food_eaten() > 0);
+  assert!(logic.score() > 0);
+  assert_eq!(events.iter().filter(|event| matches!(event, Event::Ate(_))).count() as u32, logic.food_eaten());
+}
+
+#[test]
+fn same_seed_plays_the_same_game() {
+  let mut first = training(42);
+  let mut second = training(42);
+  run(&mut first, 1500);
+  run(&mut second, 1500);
+  assert_eq!(first.score(), second.score());
+  assert_eq!(first.food_eaten(), second.food_eaten());
+  assert_eq!(first.player().head, second.player().head);
+  assert_eq!(first.player().tail, second.player().tail);
+}
+
+#[test]
+fn taking_over_into_a_wall_costs_a_life() {
+  let mut logic = training(7);
+  let lives = logic.lives();
+  // Up until the top wall, the takeover outlasts the trip.
+  logic.steer(Some(Dir::Up), None);
+  let events = run(&mut logic, 300);
+  assert_eq!(logic.lives(), lives - 1);
+  assert!(events.contains(&Event::LostLife));
+}